@@ -0,0 +1,74 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+const BASE_DELAY: Duration = Duration::from_secs(10);
+const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug)]
+pub struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self { delay: BASE_DELAY }
+    }
+
+    pub fn reset(&mut self) {
+        self.delay = BASE_DELAY;
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (delay * 2).min(MAX_DELAY);
+        delay + jitter(delay / 4)
+    }
+}
+
+fn jitter(max: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let max_millis = max.as_millis() as u64;
+    if max_millis == 0 {
+        Duration::ZERO
+    } else {
+        Duration::from_millis(random % max_millis)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Backoff, BASE_DELAY, MAX_DELAY};
+    use std::time::Duration;
+
+    fn assert_delay(delay: Duration, expected: Duration) {
+        assert!(delay >= expected, "{:?} < {:?}", delay, expected);
+        assert!(
+            delay <= expected + expected / 4,
+            "{:?} > {:?}",
+            delay,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_maximum() {
+        let mut backoff = Backoff::new();
+        assert_delay(backoff.next_delay(), BASE_DELAY);
+        assert_delay(backoff.next_delay(), BASE_DELAY * 2);
+        assert_delay(backoff.next_delay(), BASE_DELAY * 4);
+        for _ in 0..10 {
+            backoff.next_delay();
+        }
+        assert_delay(backoff.next_delay(), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_backoff_reset() {
+        let mut backoff = Backoff::new();
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.reset();
+        assert_delay(backoff.next_delay(), BASE_DELAY);
+    }
+}
@@ -0,0 +1,101 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IP address range, such as `192.30.252.0/22`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_length: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                self.prefix_matches(u32::from(network).into(), u32::from(address).into(), 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                self.prefix_matches(network.into(), address.into(), 128)
+            }
+            _ => false,
+        }
+    }
+
+    fn prefix_matches(&self, network: u128, address: u128, bits: u8) -> bool {
+        let host_bits = u32::from(bits - self.prefix_length);
+        (network ^ address).checked_shr(host_bits).unwrap_or(0) == 0
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, CidrError> {
+        let invalid = || CidrError(s.into());
+        let (network, prefix_length) = match s.split_once('/') {
+            Some((network, prefix_length)) => (network, Some(prefix_length)),
+            None => (s, None),
+        };
+        let network: IpAddr = network.parse().map_err(|_| invalid())?;
+        let max_prefix_length = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_length = match prefix_length {
+            Some(prefix_length) => prefix_length.parse().map_err(|_| invalid())?,
+            None => max_prefix_length,
+        };
+        if prefix_length > max_prefix_length {
+            return Err(invalid());
+        }
+        Ok(Self {
+            network,
+            prefix_length,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CidrError(String);
+
+impl Display for CidrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid CIDR range {:?}", self.0)
+    }
+}
+
+impl Error for CidrError {}
+
+#[cfg(test)]
+mod test {
+    use super::Cidr;
+
+    fn contains(cidr: &str, address: &str) -> bool {
+        let cidr: Cidr = cidr.parse().unwrap();
+        cidr.contains(address.parse().unwrap())
+    }
+
+    #[test]
+    fn test_ipv4() {
+        assert!(contains("192.30.252.0/22", "192.30.253.17"));
+        assert!(!contains("192.30.252.0/22", "192.30.250.17"));
+        assert!(contains("10.0.0.1", "10.0.0.1"));
+        assert!(!contains("10.0.0.1", "10.0.0.2"));
+        assert!(contains("0.0.0.0/0", "8.8.8.8"));
+    }
+
+    #[test]
+    fn test_ipv6() {
+        assert!(contains("2a0a:a440::/29", "2a0a:a445::1"));
+        assert!(!contains("2a0a:a440::/29", "2a0a:a450::1"));
+        assert!(!contains("2a0a:a440::/29", "192.30.252.1"));
+        assert!(contains("::/0", "::1"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!("192.30.252.0/33".parse::<Cidr>().is_err());
+        assert!("192.30.252/22".parse::<Cidr>().is_err());
+        assert!("::/129".parse::<Cidr>().is_err());
+        assert!("github".parse::<Cidr>().is_err());
+    }
+}
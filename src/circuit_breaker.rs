@@ -0,0 +1,110 @@
+use serde::Serialize;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests are made as usual.
+    Closed,
+    /// Requests failed too many times in a row, and aren't made until the
+    /// cooldown passes.
+    Open,
+    /// The cooldown passed, and requests are made to check whether the
+    /// service recovered. A single failure opens the circuit again.
+    HalfOpen,
+}
+
+/// Stops making requests to a service that keeps failing, so that callers
+/// don't wait for timeouts of doomed requests during outages.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            failures: 0,
+            opened_at: None,
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.cooldown => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    pub fn allows_request(&self) -> bool {
+        self.state() != CircuitState::Open
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+        self.opened_at = None;
+    }
+
+    /// Records a failed request, returning whether that opened the circuit.
+    pub fn failed(&mut self) -> bool {
+        self.failures += 1;
+        if self.state() == CircuitState::HalfOpen || self.failures == self.threshold {
+            self.opened_at = Some(Instant::now());
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CircuitBreaker, CircuitState};
+    use std::time::Duration;
+    use tokio::time;
+
+    const COOLDOWN: Duration = Duration::from_secs(60);
+
+    #[tokio::test]
+    async fn test_opens_after_consecutive_failures() {
+        time::pause();
+        let mut breaker = CircuitBreaker::new(3, COOLDOWN);
+        assert!(!breaker.failed());
+        assert!(!breaker.failed());
+        breaker.succeeded();
+        assert!(!breaker.failed());
+        assert!(!breaker.failed());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.failed());
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allows_request());
+    }
+
+    #[tokio::test]
+    async fn test_half_open_failure_opens_again() {
+        time::pause();
+        let mut breaker = CircuitBreaker::new(1, COOLDOWN);
+        assert!(breaker.failed());
+        time::advance(COOLDOWN).await;
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.allows_request());
+        assert!(breaker.failed());
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[tokio::test]
+    async fn test_half_open_success_closes() {
+        time::pause();
+        let mut breaker = CircuitBreaker::new(1, COOLDOWN);
+        breaker.failed();
+        time::advance(COOLDOWN).await;
+        breaker.succeeded();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
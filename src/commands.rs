@@ -0,0 +1,96 @@
+/// Showdown ranks, from the lowest to the highest.
+const RANKS: &[char] = &['‽', '!', ' ', '+', '%', '@', '*', '#', '&', '~'];
+
+/// Position of regular users in `RANKS`.
+const REGULAR: usize = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Mute,
+    Unmute,
+}
+
+/// Returns whether `rank` is a known Showdown rank symbol.
+pub fn is_rank(rank: char) -> bool {
+    RANKS.contains(&rank)
+}
+
+/// Returns whether a user (as sent by Showdown, with the rank symbol in
+/// front) has at least `min_rank`. Unknown ranks count as regular users.
+pub fn has_rank(user: &str, min_rank: char) -> bool {
+    let level = |rank| RANKS.iter().position(|&r| r == rank).unwrap_or(REGULAR);
+    match user.chars().next() {
+        Some(rank) => level(rank) >= level(min_rank),
+        None => false,
+    }
+}
+
+/// Parses a chat message into a command, if it's one and the user is allowed
+/// to use it.
+pub fn parse_command(user: &str, message: &str, min_rank: char) -> Option<Command> {
+    let arguments = message.trim().strip_prefix(".psdevbot ")?;
+    if !has_rank(user, min_rank) {
+        return None;
+    }
+    match arguments.trim() {
+        "mute" => Some(Command::Mute),
+        "unmute" => Some(Command::Unmute),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{has_rank, is_rank, parse_command, Command};
+
+    #[test]
+    fn test_mute() {
+        assert_eq!(
+            parse_command("#Owner", ".psdevbot mute", '%'),
+            Some(Command::Mute)
+        );
+        assert_eq!(
+            parse_command("%Driver", " .psdevbot  unmute ", '%'),
+            Some(Command::Unmute),
+        );
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        assert_eq!(parse_command("#Owner", ".psdevbot dance", '%'), None);
+        assert_eq!(parse_command("#Owner", ".psdevbotmute", '%'), None);
+        assert_eq!(parse_command("#Owner", "mute", '%'), None);
+    }
+
+    #[test]
+    fn test_rank_required() {
+        assert_eq!(parse_command(" Regular", ".psdevbot mute", '%'), None);
+        assert_eq!(parse_command("+Voice", ".psdevbot mute", '%'), None);
+        assert_eq!(parse_command("!Muted", ".psdevbot unmute", '%'), None);
+        assert_eq!(parse_command("%Driver", ".psdevbot mute", '#'), None);
+        assert_eq!(
+            parse_command("+Voice", ".psdevbot mute", '+'),
+            Some(Command::Mute),
+        );
+    }
+
+    #[test]
+    fn test_rank_ordering() {
+        let ranks = ['‽', '!', ' ', '+', '%', '@', '*', '#', '&', '~'];
+        for (i, &rank) in ranks.iter().enumerate() {
+            let user = format!("{}User", rank);
+            for (j, &min_rank) in ranks.iter().enumerate() {
+                assert_eq!(has_rank(&user, min_rank), i >= j, "{} {}", rank, min_rank);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_rank() {
+        assert!(!is_rank('?'));
+        assert!(is_rank('@'));
+        assert!(has_rank("?User", ' '));
+        assert!(!has_rank("?User", '+'));
+        assert!(!has_rank("", ' '));
+    }
+}
@@ -1,28 +1,209 @@
-use crate::github_api::GitHubApi;
+use crate::cidr::Cidr;
+use crate::commands;
+use crate::discord::DiscordWebhook;
+use crate::github_api::{ClientOptions, GitHubApi};
+use crate::template_overrides::TemplateOverrides;
+use crate::unbounded::Priority;
 use futures::lock::Mutex;
-use serde::de::{Deserializer, MapAccess, Visitor};
-use serde::Deserialize;
+use serde::de::{self, DeserializeOwned, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
 use showdown::url::Url;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
 use std::error::Error;
+use std::ffi::OsString;
 use std::fmt::{self, Formatter};
+use std::fs::File;
 use std::hash::{BuildHasher, Hash, Hasher};
-use std::slice;
+use std::path::PathBuf;
+use std::time::Duration;
 use unicase::UniCase;
 
 pub struct Config {
-    pub server: Url,
-    pub user: String,
-    pub password: String,
-    pub secret: String,
+    pub servers: Vec<ServerConfig>,
+    /// Accepted webhook secrets, more than one while the secret is rotated.
+    pub secrets: Vec<String>,
     pub port: u16,
-    default_room_name: Option<String>,
+    /// Rooms for repositories without project configuration.
+    default_rooms: Vec<String>,
     room_configuration: HashMap<String, RoomConfiguration>,
     pub github_api: Option<Mutex<GitHubApi>>,
     pub username_aliases: UsernameAliases,
+    pub repository_aliases: HashMap<String, String>,
+    pub action_verbs: ActionVerbs,
+    /// Commands used to post announcements, by room.
+    pub room_commands: RoomCommands,
+    /// Show Pokémon Showdown repositories as `server` and `client`.
+    pub showdown_repository_names: bool,
+    /// Replace `:sparkles:` style shortcodes in commit messages with emoji.
+    pub emoji_shortcodes: bool,
+    pub max_message_length: usize,
+    pub pull_request_preview_length: usize,
+    pub max_queued_messages: Option<usize>,
+    pub deduplicate_messages: bool,
+    pub per_room_throttle: bool,
+    /// Respond to webhooks only after announcements are actually sent, not
+    /// just queued.
+    pub wait_for_delivery: bool,
+    pub watchdog_timeout: Duration,
+    pub keepalive_interval: Option<Duration>,
+    pub require_known_repo: bool,
+    pub hide_merge_commits: bool,
+    /// Show consecutive commits by the same author under one header.
+    pub group_commits: bool,
+    /// Show how many lines each pushed commit added and removed, fetched
+    /// from the GitHub API.
+    pub commit_stats: bool,
+    pub commit_timestamps: CommitTimestamps,
+    /// Show Gravatar avatars from commit author emails for authors without
+    /// a GitHub account.
+    pub gravatars: bool,
+    /// Show repository descriptions and star counts in push announcements,
+    /// fetched from the GitHub API.
+    pub repository_summaries: bool,
+    /// Mention the branch in announcements of pushes to the default branch.
+    pub show_default_branch: bool,
+    pub debug: bool,
+    pub allowed_cidrs: Vec<Cidr>,
+    pub trust_forwarded_for: bool,
+    pub merge_window: Option<Duration>,
+    pub here_workaround: bool,
+    pub resolve_commit_authors: bool,
+    pub discord_webhook: Option<DiscordWebhook>,
+    /// How long repeated events for a pull request are ignored for.
+    pub pull_request_dedup_window: Duration,
+    pub announce_ready_for_review: bool,
+    pub max_body_bytes: u64,
+    /// Path segments of the webhook URL, `github/callback` by default. The
+    /// signature test endpoint replaces the last segment with `test`.
+    pub webhook_path: Vec<String>,
+    /// How many webhooks can be handled at once, further ones are rejected.
+    pub max_concurrent_webhooks: usize,
+    /// Longest push announcement HTML, longer pushes drop trailing commits.
+    pub max_html_length: usize,
+    /// Lowest Showdown rank allowed to use bot commands.
+    pub min_command_rank: char,
+    /// Events sent before other queued announcements.
+    pub priority_events: HashSet<String>,
+    pub template_overrides: TemplateOverrides,
+    /// Certificate and key to serve webhooks over HTTPS with.
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Debug)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    /// Creates a configuration from `PSDEVBOT_TLS_CERT` and
+    /// `PSDEVBOT_TLS_KEY`, which have to be set together and readable.
+    fn new(
+        cert_path: Option<OsString>,
+        key_path: Option<OsString>,
+    ) -> Result<Option<Self>, String> {
+        let (cert_path, key_path) = match (cert_path, key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                (PathBuf::from(cert_path), PathBuf::from(key_path))
+            }
+            (None, None) => return Ok(None),
+            _ => {
+                return Err("PSDEVBOT_TLS_CERT and PSDEVBOT_TLS_KEY have to be set together".into())
+            }
+        };
+        for (name, path) in [
+            ("PSDEVBOT_TLS_CERT", &cert_path),
+            ("PSDEVBOT_TLS_KEY", &key_path),
+        ] {
+            if let Err(e) = File::open(path) {
+                return Err(format!("Couldn't read {} {}: {}", name, path.display(), e));
+            }
+        }
+        Ok(Some(Self {
+            cert_path,
+            key_path,
+        }))
+    }
+}
+
+/// A Showdown server the bot connects to.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(deserialize_with = "deserialize_url")]
+    pub server: Url,
+    pub user: String,
+    #[serde(default)]
+    pub password: String,
+    /// Login server assertion sent instead of logging in with the password,
+    /// so that the password doesn't need to be stored.
+    pub assertion: Option<String>,
+    /// Rooms this server is responsible for, or `None` for every room.
+    pub rooms: Option<HashSet<String>>,
 }
 
+/// How the bot logs in to a Showdown server.
+#[derive(Debug, PartialEq)]
+pub enum Credentials<'a> {
+    Password(&'a str),
+    Assertion(&'a str),
+}
+
+impl ServerConfig {
+    /// The assertion when one is configured, otherwise the password.
+    pub fn credentials(&self) -> Credentials<'_> {
+        match &self.assertion {
+            Some(assertion) => Credentials::Assertion(assertion),
+            None => Credentials::Password(&self.password),
+        }
+    }
+
+    pub fn handles_room(&self, room: &str) -> bool {
+        match &self.rooms {
+            Some(rooms) => rooms.contains(room),
+            None => true,
+        }
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(name: &str, json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| format!("{} should be valid JSON: {}", name, e))
+}
+
+/// Splits a path like `/hooks/github/` into segments, which can only use
+/// characters that don't need escaping in URLs.
+fn parse_webhook_path(path: &str) -> Result<Vec<String>, String> {
+    let segments: Vec<String> = path
+        .trim_matches('/')
+        .split('/')
+        .map(String::from)
+        .collect();
+    let is_valid = |segment: &String| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c))
+    };
+    if !segments.iter().all(is_valid) {
+        return Err(format!("Invalid PSDEVBOT_WEBHOOK_PATH {:?}", path));
+    }
+    Ok(segments)
+}
+
+fn deserialize_url<'de, D>(deserializer: D) -> Result<Url, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let url = String::deserialize(deserializer)?;
+    Url::parse(&url).map_err(de::Error::custom)
+}
+
+/// Display names for GitHub users.
+///
+/// Lookups are case-insensitive using Unicode case folding, so `Łukasz` and
+/// `łUKASZ` refer to the same alias. Names without an alias are returned
+/// unchanged.
 #[derive(Default)]
 pub struct UsernameAliases {
     map: hashbrown::HashMap<UniCase<String>, String>,
@@ -42,6 +223,13 @@ impl UsernameAliases {
     pub fn insert(&mut self, key: String, value: String) {
         self.map.insert(UniCase::new(key), value);
     }
+
+    /// Adds all aliases from a JSON object mapping usernames to display names.
+    pub fn extend_from_json(&mut self, json: &str) -> serde_json::Result<()> {
+        let aliases: UsernameAliases = serde_json::from_str(json)?;
+        self.map.extend(aliases.map);
+        Ok(())
+    }
 }
 
 impl<'de> Deserialize<'de> for UsernameAliases {
@@ -74,6 +262,82 @@ impl<'de> Deserialize<'de> for UsernameAliases {
     }
 }
 
+/// Custom wording of event actions by event type and action, such as
+/// `{"pull_request": {"synchronize": "pushed to"}}`. Actions without custom
+/// wording use the built-in one.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct ActionVerbs {
+    events: HashMap<String, HashMap<String, String>>,
+}
+
+impl ActionVerbs {
+    pub fn get(&self, event: &str, action: &str) -> Option<&str> {
+        self.events.get(event)?.get(action).map(String::as_str)
+    }
+}
+
+/// How commit times are shown in the tooltips of pushed commits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommitTimestamps {
+    Hidden,
+    /// Like `2h ago`.
+    Relative,
+    /// In UTC, like `2021-05-01 10:00 UTC`.
+    Absolute,
+}
+
+/// Command wrapping announcements posted to a room.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub enum RoomCommand {
+    /// `addhtmlbox`, shown to everyone in the room.
+    HtmlBox,
+    /// `addrankhtmlbox`, shown to users with at least the given rank.
+    RankHtmlBox(char),
+    /// `announce`, with the announcement as plain text.
+    Announce,
+}
+
+impl TryFrom<String> for RoomCommand {
+    type Error = String;
+
+    fn try_from(command: String) -> Result<Self, String> {
+        match command.split_once(' ') {
+            None if command == "addhtmlbox" => Ok(Self::HtmlBox),
+            None if command == "announce" => Ok(Self::Announce),
+            Some(("addrankhtmlbox", rank)) => {
+                let mut chars = rank.trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(rank), None) if commands::is_rank(rank) => Ok(Self::RankHtmlBox(rank)),
+                    _ => Err(format!("invalid rank {:?} in {:?}", rank, command)),
+                }
+            }
+            _ => Err(format!(
+                "unknown command {:?}, expected addhtmlbox, addrankhtmlbox RANK or announce",
+                command,
+            )),
+        }
+    }
+}
+
+/// Commands used to post announcements by room name, such as
+/// `{"staff": "addrankhtmlbox %"}`. Other rooms use `addhtmlbox`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct RoomCommands {
+    rooms: HashMap<String, RoomCommand>,
+}
+
+impl RoomCommands {
+    pub fn get(&self, room: &str) -> RoomCommand {
+        self.rooms
+            .get(room)
+            .copied()
+            .unwrap_or(RoomCommand::HtmlBox)
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct RoomConfiguration {
@@ -81,150 +345,911 @@ pub struct RoomConfiguration {
     pub rooms: Vec<String>,
     #[serde(default)]
     pub simple_rooms: Vec<String>,
-    pub secret: Option<String>,
+    /// Rooms that get plain chat messages, for rooms where HTML boxes aren't
+    /// allowed.
+    #[serde(default)]
+    pub text_rooms: Vec<String>,
+    /// Either a single secret or a list of accepted secrets.
+    #[serde(default, deserialize_with = "deserialize_secrets")]
+    pub secret: Option<Vec<String>>,
+    #[serde(default = "default_show_avatars")]
+    pub show_avatars: bool,
+    /// Fetch GitHub profiles of commit authors.
+    #[serde(default = "default_enrich_authors")]
+    pub enrich_authors: bool,
+    pub color: Option<String>,
+    /// Names of workflows whose runs are announced, or `None` for all of them.
+    pub workflows: Option<HashSet<String>>,
+    /// Environments whose deployments are announced, or `None` for all of
+    /// them.
+    pub deployment_environments: Option<HashSet<String>>,
+    /// Names of check runs that are announced, or `None` for all of them.
+    pub check_runs: Option<HashSet<String>>,
+    /// Contexts of commit statuses that are announced, or `None` for all of
+    /// them.
+    pub status_contexts: Option<HashSet<String>>,
+    /// Only announce pushed commits touching files starting with one of
+    /// these paths, or `None` for all commits.
+    pub path_prefixes: Option<Vec<String>>,
+    /// Branch whose pushes are announced, instead of the default branch. Can
+    /// be a glob pattern, like `*` for every branch.
+    pub announce_branch: Option<String>,
+    /// Glob patterns of branches whose pushes are never announced, even when
+    /// they match `announce_branch`.
+    #[serde(default)]
+    pub ignore_branches: Vec<String>,
+    #[serde(default)]
+    pub announce_stars: bool,
+    #[serde(default)]
+    pub announce_forks: bool,
+    #[serde(default)]
+    pub announce_discussions: bool,
+    #[serde(default)]
+    pub announce_wiki: bool,
+    /// Announce collaborators being added and removed.
+    #[serde(default)]
+    pub announce_members: bool,
+    /// Only announce stars when the star count is a multiple of this.
+    pub star_milestone: Option<u32>,
+    /// Overrides `PSDEVBOT_PR_DEDUP_SECS` for this repository.
+    pub pull_request_dedup_secs: Option<u64>,
+    /// Text shown before every HTML announcement.
+    pub prefix: Option<String>,
+    /// Text shown after every HTML announcement.
+    pub suffix: Option<String>,
+    /// Use `prefix` and `suffix` as HTML instead of escaping them.
+    #[serde(default)]
+    pub raw_affixes: bool,
+}
+
+fn deserialize_secrets<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Secrets {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(
+        Option::<Secrets>::deserialize(deserializer)?.map(|secrets| match secrets {
+            Secrets::One(secret) => vec![secret],
+            Secrets::Many(secrets) => secrets,
+        }),
+    )
+}
+
+fn default_show_avatars() -> bool {
+    true
+}
+
+fn default_enrich_authors() -> bool {
+    true
 }
 
 pub struct RoomConfigurationRef<'a> {
     pub rooms: &'a [String],
     pub simple_rooms: &'a [String],
-    pub secret: &'a str,
+    pub text_rooms: &'a [String],
+    pub secrets: &'a [String],
+    pub show_avatars: bool,
+    pub enrich_authors: bool,
+    pub color: &'a str,
+    pub workflows: Option<&'a HashSet<String>>,
+    pub deployment_environments: Option<&'a HashSet<String>>,
+    pub check_runs: Option<&'a HashSet<String>>,
+    pub status_contexts: Option<&'a HashSet<String>>,
+    pub path_prefixes: Option<&'a [String]>,
+    pub announce_branch: Option<&'a str>,
+    pub ignore_branches: &'a [String],
+    pub announce_stars: bool,
+    pub announce_forks: bool,
+    pub announce_discussions: bool,
+    pub announce_wiki: bool,
+    pub announce_members: bool,
+    pub star_milestone: Option<u32>,
+    pub pull_request_dedup_window: Duration,
+    pub prefix: Option<&'a str>,
+    pub suffix: Option<&'a str>,
+    pub raw_affixes: bool,
+}
+
+const DEFAULT_REPOSITORY_COLOR: &str = "FF00FF";
+
+/// Keeps push announcements, along with the command and room name, within
+/// the size of messages Showdown accepts.
+const DEFAULT_MAX_HTML_LENGTH: usize = 100_000 - 100;
+
+/// Room configuration as exposed by the `/rooms` debugging endpoint, without
+/// any secrets.
+#[derive(Debug, Serialize)]
+pub struct RoomsSummary {
+    default_rooms: Vec<String>,
+    projects: BTreeMap<String, ProjectSummary>,
+    all_rooms: BTreeSet<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectSummary {
+    rooms: Vec<String>,
+    simple_rooms: Vec<String>,
+    text_rooms: Vec<String>,
+    has_secret: bool,
+    show_avatars: bool,
+    enrich_authors: bool,
+    color: String,
+    workflows: Option<BTreeSet<String>>,
+    deployment_environments: Option<BTreeSet<String>>,
+    check_runs: Option<BTreeSet<String>>,
+    status_contexts: Option<BTreeSet<String>>,
+    path_prefixes: Option<Vec<String>>,
+    announce_branch: Option<String>,
+    ignore_branches: Vec<String>,
+    announce_stars: bool,
+    announce_forks: bool,
+    announce_discussions: bool,
+    announce_wiki: bool,
+    announce_members: bool,
+    star_milestone: Option<u32>,
+    pull_request_dedup_secs: Option<u64>,
+    prefix: Option<String>,
+    suffix: Option<String>,
+    raw_affixes: bool,
 }
 
 impl Config {
     pub fn new() -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let server = Url::parse(&env::var("PSDEVBOT_SERVER")?)?;
-        let user = env::var("PSDEVBOT_USER")?;
-        let password = env::var("PSDEVBOT_PASSWORD")?;
-        let secret = env::var("PSDEVBOT_SECRET")?;
+        let servers = match env::var("PSDEVBOT_SERVERS") {
+            Ok(json) => parse_json("PSDEVBOT_SERVERS", &json)?,
+            Err(_) => {
+                let assertion = env::var("PSDEVBOT_ASSERTION").ok();
+                let password = match env::var("PSDEVBOT_PASSWORD") {
+                    Ok(password) => password,
+                    Err(_) if assertion.is_some() => String::new(),
+                    Err(e) => return Err(e.into()),
+                };
+                vec![ServerConfig {
+                    server: Url::parse(&env::var("PSDEVBOT_SERVER")?)?,
+                    user: env::var("PSDEVBOT_USER")?,
+                    password,
+                    assertion,
+                    rooms: None,
+                }]
+            }
+        };
+        if servers.is_empty() {
+            return Err("PSDEVBOT_SERVERS needs to contain at least one server".into());
+        }
+        let secrets = match env::var("PSDEVBOT_SECRETS") {
+            Ok(json) => parse_json("PSDEVBOT_SECRETS", &json)?,
+            Err(_) => vec![env::var("PSDEVBOT_SECRET")?],
+        };
         let port = match env::var("PSDEVBOT_PORT") {
             Ok(port) => port.parse()?,
             Err(_) => 3030,
         };
-        let default_room_name = env::var("PSDEVBOT_ROOM").ok();
-        let room_configuration = env::var("PSDEVBOT_PROJECT_CONFIGURATION")
-            .map(|json| {
-                serde_json::from_str(&json)
-                    .expect("PSDEVBOT_PROJECT_CONFIGURATION should be valid JSON")
+        let default_rooms: Vec<_> = match env::var("PSDEVBOT_ROOM") {
+            Ok(rooms) => rooms
+                .split(',')
+                .map(|room| room.trim().to_string())
+                .filter(|room| !room.is_empty())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let room_configuration = match env::var("PSDEVBOT_PROJECT_CONFIGURATION") {
+            Ok(json) => Some(parse_json("PSDEVBOT_PROJECT_CONFIGURATION", &json)?),
+            Err(_) => None,
+        };
+        if default_rooms.is_empty() && room_configuration.is_none() {
+            return Err(
+                "At least one of PSDEVBOT_ROOM or PSDEVBOT_PROJECT_CONFIGURATION needs to be provided"
+                    .into(),
+            );
+        }
+        let room_configuration: HashMap<String, RoomConfiguration> =
+            room_configuration.unwrap_or_default();
+        for (name, project) in &room_configuration {
+            if let Some(color) = &project.color {
+                if !is_hex_color(color) {
+                    return Err(format!("Invalid color {:?} for project {}", color, name).into());
+                }
+            }
+        }
+        let mut client_options = ClientOptions::default();
+        if let Ok(ms) = env::var("PSDEVBOT_GITHUB_TIMEOUT_MS") {
+            client_options.timeout = Duration::from_millis(ms.parse()?);
+        }
+        client_options.connect_timeout = env::var("PSDEVBOT_GITHUB_CONNECT_TIMEOUT_MS")
+            .ok()
+            .map(|ms| ms.parse().map(Duration::from_millis))
+            .transpose()?;
+        if let Ok(size) = env::var("PSDEVBOT_GITHUB_CACHE_SIZE") {
+            client_options.cache_size = size.parse()?;
+            if client_options.cache_size == 0 {
+                return Err("PSDEVBOT_GITHUB_CACHE_SIZE needs to be positive".into());
+            }
+        }
+        let github_user = env::var("PSDEVBOT_GITHUB_API_USER").ok();
+        let github_password = env::var("PSDEVBOT_GITHUB_API_PASSWORD").ok();
+        if github_user.is_some() != github_password.is_some() {
+            return Err(
+                "PSDEVBOT_GITHUB_API_USER and PSDEVBOT_GITHUB_API_PASSWORD have to be set together"
+                    .into(),
+            );
+        }
+        let github_api = github_user.zip(github_password).map(|(user, password)| {
+            let base_url = env::var("PSDEVBOT_GITHUB_API_BASE")
+                .unwrap_or_else(|_| "https://api.github.com".into());
+            let github_api = GitHubApi::with_base_url(user, password, base_url, client_options);
+            Mutex::new(match env::var_os("PSDEVBOT_GITHUB_CACHE_FILE") {
+                Some(path) => github_api.with_cache_file(path.into()),
+                None => github_api,
             })
-            .ok();
-        if default_room_name.is_none() && room_configuration.is_none() {
-            panic!("At least one of PSDEVBOT_ROOM or PSDEVBOT_PROJECT_CONFIGURATION needs to be provided");
+        });
+        let mut username_aliases = UsernameAliases::default();
+        if let Ok(json) = env::var("PSDEVBOT_USERNAME_ALIASES") {
+            username_aliases
+                .extend_from_json(&json)
+                .map_err(|e| format!("PSDEVBOT_USERNAME_ALIASES should be valid JSON: {}", e))?;
         }
-        let github_api = env::var("PSDEVBOT_GITHUB_API_USER").ok().and_then(|user| {
-            let password = env::var("PSDEVBOT_GITHUB_API_PASSWORD").ok()?;
-            Some(Mutex::new(GitHubApi::new(user, password)))
+        let repository_aliases = match env::var("PSDEVBOT_REPO_NAME_ALIASES") {
+            Ok(json) => parse_json("PSDEVBOT_REPO_NAME_ALIASES", &json)?,
+            Err(_) => HashMap::new(),
+        };
+        let action_verbs = match env::var("PSDEVBOT_ACTION_VERBS") {
+            Ok(json) => parse_json("PSDEVBOT_ACTION_VERBS", &json)?,
+            Err(_) => ActionVerbs::default(),
+        };
+        let room_commands = match env::var("PSDEVBOT_ROOM_COMMANDS") {
+            Ok(json) => parse_json("PSDEVBOT_ROOM_COMMANDS", &json)?,
+            Err(_) => RoomCommands::default(),
+        };
+        let showdown_repository_names = env_flag("PSDEVBOT_SHOWDOWN_REPO_NAMES", true)?;
+        let emoji_shortcodes = env_flag("PSDEVBOT_EMOJI_SHORTCODES", false)?;
+        let max_message_length = match env::var("PSDEVBOT_MAX_MESSAGE_LENGTH") {
+            Ok(length) => length.parse()?,
+            Err(_) => 120,
+        };
+        let pull_request_preview_length = match env::var("PSDEVBOT_PR_PREVIEW_LENGTH") {
+            Ok(length) => length.parse()?,
+            Err(_) => 200,
+        };
+        let max_queued_messages = env::var("PSDEVBOT_MAX_QUEUED_MESSAGES")
+            .ok()
+            .map(|max| max.parse())
+            .transpose()?;
+        let deduplicate_messages = env_flag("PSDEVBOT_DEDUPLICATE_MESSAGES", false)?;
+        let per_room_throttle = env_flag("PSDEVBOT_PER_ROOM_THROTTLE", false)?;
+        let wait_for_delivery = env_flag("PSDEVBOT_WAIT_FOR_DELIVERY", false)?;
+        let watchdog_timeout = Duration::from_secs(match env::var("PSDEVBOT_WATCHDOG_SECS") {
+            Ok(secs) => secs.parse()?,
+            Err(_) => 5 * 60,
         });
-        let username_aliases = env::var("PSDEVBOT_USERNAME_ALIASES")
-            .map(|json| {
-                serde_json::from_str(&json).expect("PSDEVBOT_USERNAME_ALIASES should be valid JSON")
-            })
-            .unwrap_or_default();
-        Ok(Self {
-            server,
-            user,
-            password,
-            secret,
+        let keepalive_interval = env::var("PSDEVBOT_KEEPALIVE_SECS")
+            .ok()
+            .map(|secs| secs.parse().map(Duration::from_secs))
+            .transpose()?
+            .filter(|interval| !interval.is_zero());
+        let require_known_repo = env_flag("PSDEVBOT_REQUIRE_KNOWN_REPO", false)?;
+        let hide_merge_commits = env_flag("PSDEVBOT_HIDE_MERGE_COMMITS", false)?;
+        let group_commits = env_flag("PSDEVBOT_GROUP_COMMITS", false)?;
+        let commit_stats = env_flag("PSDEVBOT_COMMIT_STATS", false)?;
+        let commit_timestamps = match env::var("PSDEVBOT_COMMIT_TIMESTAMPS") {
+            Ok(mode) => match mode.as_str() {
+                "relative" => CommitTimestamps::Relative,
+                "absolute" => CommitTimestamps::Absolute,
+                _ => {
+                    return Err(format!(
+                        "PSDEVBOT_COMMIT_TIMESTAMPS should be relative or absolute, got {:?}",
+                        mode,
+                    )
+                    .into())
+                }
+            },
+            Err(_) => CommitTimestamps::Hidden,
+        };
+        let gravatars = env_flag("PSDEVBOT_GRAVATARS", false)?;
+        let repository_summaries = env_flag("PSDEVBOT_REPOSITORY_SUMMARIES", false)?;
+        let show_default_branch = env_flag("PSDEVBOT_SHOW_DEFAULT_BRANCH", false)?;
+        let debug = env_flag("PSDEVBOT_DEBUG", false)?;
+        let allowed_cidrs = match env::var("PSDEVBOT_ALLOWED_CIDRS") {
+            Ok(cidrs) => cidrs
+                .split(',')
+                .map(|cidr| cidr.trim().parse())
+                .collect::<Result<_, _>>()?,
+            Err(_) => Vec::new(),
+        };
+        let trust_forwarded_for = env_flag("PSDEVBOT_TRUST_FORWARDED_FOR", false)?;
+        let merge_window = env::var("PSDEVBOT_MERGE_WINDOW_MS")
+            .ok()
+            .map(|ms| ms.parse().map(Duration::from_millis))
+            .transpose()?
+            .filter(|window| !window.is_zero());
+        let here_workaround = env_flag("PSDEVBOT_HERE_WORKAROUND", true)?;
+        let resolve_commit_authors = env_flag("PSDEVBOT_RESOLVE_COMMIT_AUTHORS", false)?;
+        let discord_webhook = env::var("PSDEVBOT_DISCORD_WEBHOOK")
+            .ok()
+            .map(DiscordWebhook::new);
+        let pull_request_dedup_window =
+            Duration::from_secs(match env::var("PSDEVBOT_PR_DEDUP_SECS") {
+                Ok(secs) => secs.parse()?,
+                Err(_) => 10 * 60,
+            });
+        let announce_ready_for_review = env_flag("PSDEVBOT_ANNOUNCE_READY_FOR_REVIEW", false)?;
+        let max_body_bytes = match env::var("PSDEVBOT_MAX_BODY_BYTES") {
+            Ok(bytes) => bytes.parse()?,
+            Err(_) => 5 * 1024 * 1024,
+        };
+        let webhook_path = match env::var("PSDEVBOT_WEBHOOK_PATH") {
+            Ok(path) => parse_webhook_path(&path)?,
+            Err(_) => vec!["github".into(), "callback".into()],
+        };
+        let max_concurrent_webhooks = match env::var("PSDEVBOT_MAX_CONCURRENT_WEBHOOKS") {
+            Ok(count) => count.parse()?,
+            Err(_) => 16,
+        };
+        let max_html_length = match env::var("PSDEVBOT_MAX_HTML_LENGTH") {
+            Ok(length) => length.parse()?,
+            Err(_) => DEFAULT_MAX_HTML_LENGTH,
+        };
+        let min_command_rank = match env::var("PSDEVBOT_MIN_COMMAND_RANK") {
+            Ok(rank) => match rank.parse() {
+                Ok(rank) if commands::is_rank(rank) => rank,
+                _ => {
+                    return Err(
+                        format!("Invalid rank {:?} in PSDEVBOT_MIN_COMMAND_RANK", rank).into(),
+                    )
+                }
+            },
+            Err(_) => '%',
+        };
+        let tls = TlsConfig::new(
+            env::var_os("PSDEVBOT_TLS_CERT"),
+            env::var_os("PSDEVBOT_TLS_KEY"),
+        )?;
+        let priority_events = match env::var("PSDEVBOT_PRIORITY_EVENTS") {
+            Ok(events) => events
+                .split(',')
+                .map(|event| event.trim().to_string())
+                .collect(),
+            Err(_) => HashSet::new(),
+        };
+        let template_overrides = match env::var_os("PSDEVBOT_TEMPLATE_DIR") {
+            Some(directory) => TemplateOverrides::load(directory.as_ref())?,
+            None => TemplateOverrides::default(),
+        };
+        let config = Self {
+            servers,
+            secrets,
             port,
-            default_room_name,
-            room_configuration: room_configuration.unwrap_or_default(),
+            default_rooms,
+            room_configuration,
             github_api,
             username_aliases,
-        })
+            repository_aliases,
+            action_verbs,
+            room_commands,
+            showdown_repository_names,
+            emoji_shortcodes,
+            max_message_length,
+            pull_request_preview_length,
+            max_queued_messages,
+            deduplicate_messages,
+            per_room_throttle,
+            wait_for_delivery,
+            watchdog_timeout,
+            keepalive_interval,
+            require_known_repo,
+            hide_merge_commits,
+            group_commits,
+            commit_stats,
+            commit_timestamps,
+            gravatars,
+            repository_summaries,
+            show_default_branch,
+            debug,
+            allowed_cidrs,
+            trust_forwarded_for,
+            merge_window,
+            here_workaround,
+            resolve_commit_authors,
+            discord_webhook,
+            pull_request_dedup_window,
+            announce_ready_for_review,
+            max_body_bytes,
+            webhook_path,
+            max_concurrent_webhooks,
+            max_html_length,
+            min_command_rank,
+            priority_events,
+            template_overrides,
+            tls,
+        };
+        if let Some(room) = config.unhandled_room() {
+            return Err(format!("Room {} isn't handled by any server", room).into());
+        }
+        Ok(config)
+    }
+
+    fn unhandled_room(&self) -> Option<&str> {
+        self.all_rooms()
+            .into_iter()
+            .find(|room| !self.servers.iter().any(|server| server.handles_room(room)))
+    }
+
+    /// Problems that don't prevent the bot from starting, but likely cause
+    /// announcements to be lost.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut projects: Vec<_> = self.room_configuration.iter().collect();
+        projects.sort_unstable_by_key(|(name, _)| *name);
+        for (name, project) in projects {
+            if project.rooms.is_empty()
+                && project.simple_rooms.is_empty()
+                && project.text_rooms.is_empty()
+            {
+                problems.push(format!("Project {} doesn't announce to any room", name));
+            }
+        }
+        problems
     }
 
     pub fn all_rooms(&self) -> HashSet<&str> {
         self.room_configuration
             .values()
-            .flat_map(|r| r.rooms.iter().chain(&r.simple_rooms))
-            .chain(&self.default_room_name)
+            .flat_map(|r| r.rooms.iter().chain(&r.simple_rooms).chain(&r.text_rooms))
+            .chain(&self.default_rooms)
             .map(String::as_str)
             .collect()
     }
 
-    pub fn rooms_for(&self, name: &str) -> RoomConfigurationRef<'_> {
+    pub fn rooms_summary(&self) -> RoomsSummary {
+        RoomsSummary {
+            default_rooms: self.default_rooms.clone(),
+            projects: self
+                .room_configuration
+                .iter()
+                .map(|(name, project)| {
+                    let summary = ProjectSummary {
+                        rooms: project.rooms.clone(),
+                        simple_rooms: project.simple_rooms.clone(),
+                        text_rooms: project.text_rooms.clone(),
+                        has_secret: project.secret.is_some(),
+                        show_avatars: project.show_avatars,
+                        enrich_authors: project.enrich_authors,
+                        color: project
+                            .color
+                            .as_deref()
+                            .unwrap_or(DEFAULT_REPOSITORY_COLOR)
+                            .into(),
+                        workflows: project
+                            .workflows
+                            .as_ref()
+                            .map(|workflows| workflows.iter().cloned().collect()),
+                        deployment_environments: project
+                            .deployment_environments
+                            .as_ref()
+                            .map(|environments| environments.iter().cloned().collect()),
+                        check_runs: project
+                            .check_runs
+                            .as_ref()
+                            .map(|check_runs| check_runs.iter().cloned().collect()),
+                        status_contexts: project
+                            .status_contexts
+                            .as_ref()
+                            .map(|contexts| contexts.iter().cloned().collect()),
+                        path_prefixes: project.path_prefixes.clone(),
+                        announce_branch: project.announce_branch.clone(),
+                        ignore_branches: project.ignore_branches.clone(),
+                        announce_stars: project.announce_stars,
+                        announce_forks: project.announce_forks,
+                        announce_discussions: project.announce_discussions,
+                        announce_wiki: project.announce_wiki,
+                        announce_members: project.announce_members,
+                        star_milestone: project.star_milestone,
+                        pull_request_dedup_secs: project.pull_request_dedup_secs,
+                        prefix: project.prefix.clone(),
+                        suffix: project.suffix.clone(),
+                        raw_affixes: project.raw_affixes,
+                    };
+                    (name.clone(), summary)
+                })
+                .collect(),
+            all_rooms: self.all_rooms().into_iter().map(String::from).collect(),
+        }
+    }
+
+    pub fn event_priority(&self, event: &str) -> Priority {
+        if self.priority_events.contains(event) {
+            Priority::High
+        } else {
+            Priority::Normal
+        }
+    }
+
+    /// Returns whether a repository is listed in the project configuration.
+    pub fn is_configured(&self, name: &str) -> bool {
+        self.room_configuration.contains_key(name)
+    }
+
+    pub fn rooms_for(&self, name: &str) -> Option<RoomConfigurationRef<'_>> {
         if let Some(RoomConfiguration {
             rooms,
             simple_rooms,
+            text_rooms,
             secret,
+            show_avatars,
+            enrich_authors,
+            color,
+            workflows,
+            deployment_environments,
+            check_runs,
+            status_contexts,
+            path_prefixes,
+            announce_branch,
+            ignore_branches,
+            announce_stars,
+            announce_forks,
+            announce_discussions,
+            announce_wiki,
+            announce_members,
+            star_milestone,
+            pull_request_dedup_secs,
+            prefix,
+            suffix,
+            raw_affixes,
         }) = self.room_configuration.get(name)
         {
-            RoomConfigurationRef {
+            Some(RoomConfigurationRef {
                 rooms,
                 simple_rooms,
-                secret: secret.as_deref().unwrap_or(&self.secret),
-            }
+                text_rooms,
+                secrets: secret.as_deref().unwrap_or(&self.secrets),
+                show_avatars: *show_avatars,
+                enrich_authors: *enrich_authors,
+                color: color.as_deref().unwrap_or(DEFAULT_REPOSITORY_COLOR),
+                workflows: workflows.as_ref(),
+                deployment_environments: deployment_environments.as_ref(),
+                check_runs: check_runs.as_ref(),
+                status_contexts: status_contexts.as_ref(),
+                path_prefixes: path_prefixes.as_deref(),
+                announce_branch: announce_branch.as_deref(),
+                ignore_branches,
+                announce_stars: *announce_stars,
+                announce_forks: *announce_forks,
+                announce_discussions: *announce_discussions,
+                announce_wiki: *announce_wiki,
+                announce_members: *announce_members,
+                star_milestone: *star_milestone,
+                pull_request_dedup_window: match pull_request_dedup_secs {
+                    Some(secs) => Duration::from_secs(*secs),
+                    None => self.pull_request_dedup_window,
+                },
+                prefix: prefix.as_deref(),
+                suffix: suffix.as_deref(),
+                raw_affixes: *raw_affixes,
+            })
+        } else if self.require_known_repo {
+            None
         } else {
-            RoomConfigurationRef {
-                rooms: self
-                    .default_room_name
-                    .as_ref()
-                    .map(slice::from_ref)
-                    .unwrap_or_default(),
+            Some(RoomConfigurationRef {
+                rooms: &self.default_rooms,
                 simple_rooms: &[],
-                secret: &self.secret,
-            }
+                text_rooms: &[],
+                secrets: &self.secrets,
+                show_avatars: true,
+                enrich_authors: true,
+                color: DEFAULT_REPOSITORY_COLOR,
+                workflows: None,
+                deployment_environments: None,
+                check_runs: None,
+                status_contexts: None,
+                path_prefixes: None,
+                announce_branch: None,
+                ignore_branches: &[],
+                announce_stars: false,
+                announce_forks: false,
+                announce_discussions: false,
+                announce_wiki: false,
+                announce_members: false,
+                star_milestone: None,
+                pull_request_dedup_window: self.pull_request_dedup_window,
+                prefix: None,
+                suffix: None,
+                raw_affixes: false,
+            })
         }
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::{Config, RoomConfiguration, UsernameAliases};
-    use std::collections::HashMap;
+fn is_hex_color(color: &str) -> bool {
+    matches!(color.len(), 3 | 6) && color.bytes().all(|b| b.is_ascii_hexdigit())
+}
 
-    fn base_config() -> Config {
-        Config {
+fn env_flag(name: &str, default: bool) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    match env::var(name) {
+        Ok(value) => Ok(value.parse()?),
+        Err(_) => Ok(default),
+    }
+}
+
+/// A configuration without any projects, used by tests.
+#[cfg(test)]
+pub fn test_config(default_room_name: Option<&str>) -> Config {
+    Config {
+        servers: vec![ServerConfig {
             server: "wss://localhost/showdown/websocket".parse().unwrap(),
             user: "".into(),
             password: "".into(),
-            secret: "".into(),
-            port: 3030,
-            default_room_name: None,
-            room_configuration: HashMap::new(),
-            github_api: None,
-            username_aliases: UsernameAliases::default(),
-        }
+            assertion: None,
+            rooms: None,
+        }],
+        secrets: vec![],
+        port: 3030,
+        default_rooms: default_room_name.into_iter().map(String::from).collect(),
+        room_configuration: HashMap::new(),
+        github_api: None,
+        username_aliases: UsernameAliases::default(),
+        repository_aliases: HashMap::new(),
+        action_verbs: ActionVerbs::default(),
+        room_commands: RoomCommands::default(),
+        showdown_repository_names: true,
+        emoji_shortcodes: false,
+        max_message_length: 120,
+        pull_request_preview_length: 200,
+        max_queued_messages: None,
+        deduplicate_messages: false,
+        per_room_throttle: false,
+        wait_for_delivery: false,
+        watchdog_timeout: Duration::from_secs(5 * 60),
+        keepalive_interval: None,
+        require_known_repo: false,
+        hide_merge_commits: false,
+        group_commits: false,
+        commit_stats: false,
+        commit_timestamps: CommitTimestamps::Hidden,
+        gravatars: false,
+        repository_summaries: false,
+        show_default_branch: false,
+        debug: false,
+        allowed_cidrs: Vec::new(),
+        trust_forwarded_for: false,
+        merge_window: None,
+        here_workaround: true,
+        resolve_commit_authors: false,
+        discord_webhook: None,
+        pull_request_dedup_window: Duration::from_secs(10 * 60),
+        announce_ready_for_review: false,
+        max_body_bytes: 5 * 1024 * 1024,
+        webhook_path: vec!["github".into(), "callback".into()],
+        max_concurrent_webhooks: 16,
+        max_html_length: DEFAULT_MAX_HTML_LENGTH,
+        min_command_rank: '%',
+        priority_events: HashSet::new(),
+        template_overrides: TemplateOverrides::default(),
+        tls: None,
+    }
+}
+
+/// Adds a project configured like in `PSDEVBOT_PROJECT_CONFIGURATION`, used
+/// by tests.
+#[cfg(test)]
+pub fn add_test_project(config: &mut Config, name: &str, json: &str) {
+    let project = serde_json::from_str(json).unwrap();
+    config.room_configuration.insert(name.into(), project);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        add_test_project, is_hex_color, parse_webhook_path, test_config, Credentials, RoomCommand,
+        RoomCommands, RoomConfiguration, ServerConfig, TlsConfig, UsernameAliases,
+    };
+    use crate::unbounded::Priority;
+    use std::collections::HashMap;
+    use std::{env, fs, process};
+
+    #[test]
+    fn test_event_priority() {
+        let mut config = test_config(None);
+        config.priority_events.insert("workflow_run".into());
+        assert_eq!(config.event_priority("workflow_run"), Priority::High);
+        assert_eq!(config.event_priority("push"), Priority::Normal);
     }
 
     #[test]
     fn test_all_rooms_default_room() {
-        let mut config = base_config();
-        config.default_room_name = Some("room".into());
+        let mut config = test_config(None);
+        config.default_rooms = vec!["room".into()];
         let mut rooms: Vec<_> = config.all_rooms().into_iter().collect();
         rooms.sort_unstable();
         assert_eq!(rooms, ["room"]);
     }
 
+    #[test]
+    fn test_several_default_rooms() {
+        let mut config = test_config(None);
+        config.default_rooms = vec!["a".into(), "b".into()];
+        let mut rooms: Vec<_> = config.all_rooms().into_iter().collect();
+        rooms.sort_unstable();
+        assert_eq!(rooms, ["a", "b"]);
+        let room_configuration = config.rooms_for("Unknown/Project").unwrap();
+        assert_eq!(room_configuration.rooms, ["a", "b"]);
+    }
+
     #[test]
     fn test_all_rooms_room_configuration() {
-        let mut config = base_config();
+        let mut config = test_config(None);
+        add_test_project(&mut config, "Project", r#"{"rooms": ["a", "b"]}"#);
+        add_test_project(&mut config, "AnotherProject", r#"{"rooms": ["b", "c"]}"#);
+        add_test_project(
+            &mut config,
+            "StupidProject",
+            r#"{"simple_rooms": ["d"], "text_rooms": ["e"]}"#,
+        );
+        let mut rooms: Vec<_> = config.all_rooms().into_iter().collect();
+        rooms.sort_unstable();
+        assert_eq!(rooms, ["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn test_rooms_for_unknown_repository() {
+        let mut config = test_config(None);
+        config.default_rooms = vec!["room".into()];
+        add_test_project(&mut config, "Known/Project", r#"{"rooms": ["a"]}"#);
+        assert_eq!(config.rooms_for("Unknown/Project").unwrap().rooms, ["room"]);
+        config.require_known_repo = true;
+        assert!(config.rooms_for("Unknown/Project").is_none());
+        assert_eq!(config.rooms_for("Known/Project").unwrap().rooms, ["a"]);
+    }
+
+    #[test]
+    fn test_rooms_summary_hides_secrets() {
+        let mut config = test_config(None);
+        config.default_rooms = vec!["room".into()];
+        add_test_project(
+            &mut config,
+            "Project",
+            r#"{"rooms": ["a"], "secret": "hunter2"}"#,
+        );
+        let json = serde_json::to_value(config.rooms_summary()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "default_rooms": ["room"],
+                "projects": {
+                    "Project": {
+                        "rooms": ["a"],
+                        "simple_rooms": [],
+                        "text_rooms": [],
+                        "has_secret": true,
+                        "show_avatars": true,
+                        "enrich_authors": true,
+                        "color": "FF00FF",
+                        "workflows": null,
+                        "deployment_environments": null,
+                        "check_runs": null,
+                        "status_contexts": null,
+                        "path_prefixes": null,
+                        "announce_branch": null,
+                        "ignore_branches": [],
+                        "announce_stars": false,
+                        "announce_forks": false,
+                        "announce_discussions": false,
+                        "announce_wiki": false,
+                        "announce_members": false,
+                        "star_milestone": null,
+                        "pull_request_dedup_secs": null,
+                        "prefix": null,
+                        "suffix": null,
+                        "raw_affixes": false,
+                    },
+                },
+                "all_rooms": ["a", "room"],
+            }),
+        );
+    }
+
+    #[test]
+    fn test_project_secrets() {
+        let projects: HashMap<String, RoomConfiguration> = serde_json::from_str(
+            r#"{"a": {"secret": "old"}, "b": {"secret": ["old", "new"]}, "c": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(projects["a"].secret, Some(vec!["old".to_string()]));
+        assert_eq!(
+            projects["b"].secret,
+            Some(vec!["old".to_string(), "new".to_string()]),
+        );
+        assert_eq!(projects["c"].secret, None);
+    }
+
+    #[test]
+    fn test_unhandled_room() {
+        let mut config = test_config(None);
+        config.default_rooms = vec!["room".into()];
+        assert_eq!(config.unhandled_room(), None);
+        config.servers[0].rooms = Some(["other".to_string()].into_iter().collect());
+        assert_eq!(config.unhandled_room(), Some("room"));
+    }
+
+    #[test]
+    fn test_problems() {
+        let mut config = test_config(Some("room"));
+        assert!(config.problems().is_empty());
         config.room_configuration.insert(
             "Project".into(),
-            RoomConfiguration {
-                rooms: vec!["a".into(), "b".into()],
-                simple_rooms: vec![],
-                secret: None,
-            },
+            serde_json::from_str(r#"{"rooms": []}"#).unwrap(),
         );
-        config.room_configuration.insert(
-            "AnotherProject".into(),
-            RoomConfiguration {
-                rooms: vec!["b".into(), "c".into()],
-                simple_rooms: vec![],
-                secret: None,
-            },
+        assert_eq!(
+            config.problems(),
+            ["Project Project doesn't announce to any room"],
         );
-        config.room_configuration.insert(
-            "StupidProject".into(),
-            RoomConfiguration {
-                rooms: vec![],
-                simple_rooms: vec!["d".into()],
-                secret: None,
-            },
+    }
+
+    #[test]
+    fn test_servers_json() {
+        let servers: Vec<ServerConfig> = serde_json::from_str(
+            r#"[{"server": "wss://localhost/", "user": "a", "password": "b", "rooms": ["x"]}]"#,
+        )
+        .unwrap();
+        assert!(servers[0].handles_room("x"));
+        assert!(!servers[0].handles_room("y"));
+        assert!(serde_json::from_str::<Vec<ServerConfig>>(
+            r#"[{"server": "not a url", "user": "a", "password": "b"}]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let servers: Vec<ServerConfig> = serde_json::from_str(
+            r#"[
+                {"server": "wss://localhost/", "user": "a", "password": "b"},
+                {"server": "wss://localhost/", "user": "a", "assertion": "c"},
+                {"server": "wss://localhost/", "user": "a", "password": "b", "assertion": "c"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(servers[0].credentials(), Credentials::Password("b"));
+        assert_eq!(servers[1].credentials(), Credentials::Assertion("c"));
+        assert_eq!(servers[2].credentials(), Credentials::Assertion("c"));
+    }
+
+    #[test]
+    fn test_room_commands() {
+        let commands: RoomCommands = serde_json::from_str(
+            r#"{"staff": "addrankhtmlbox %", "lobby": "announce", "dev": "addhtmlbox"}"#,
+        )
+        .unwrap();
+        assert_eq!(commands.get("staff"), RoomCommand::RankHtmlBox('%'));
+        assert_eq!(commands.get("lobby"), RoomCommand::Announce);
+        assert_eq!(commands.get("dev"), RoomCommand::HtmlBox);
+        assert_eq!(commands.get("other"), RoomCommand::HtmlBox);
+        for invalid in [r#"{"a": "addrankhtmlbox x"}"#, r#"{"a": "declare"}"#] {
+            assert!(serde_json::from_str::<RoomCommands>(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_webhook_path() {
+        assert_eq!(
+            parse_webhook_path("/hooks/github/").unwrap(),
+            ["hooks", "github"],
         );
-        let mut rooms: Vec<_> = config.all_rooms().into_iter().collect();
-        rooms.sort_unstable();
-        assert_eq!(rooms, ["a", "b", "c", "d"]);
+        assert_eq!(parse_webhook_path("callback").unwrap(), ["callback"]);
+        for invalid in ["", "/", "hooks//github", "hooks/git hub", "hooks/%2F"] {
+            assert!(parse_webhook_path(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_is_hex_color() {
+        assert!(is_hex_color("FF00FF"));
+        assert!(is_hex_color("0af"));
+        assert!(!is_hex_color("red"));
+        assert!(!is_hex_color("FF00FF'"));
     }
 
     #[test]
@@ -234,4 +1259,35 @@ mod test {
         assert_eq!(username_aliases.get("a"), "Awesome");
         assert_eq!(username_aliases.get("b"), "b");
     }
+
+    #[test]
+    fn test_unicode_username_aliases() {
+        let mut username_aliases = UsernameAliases::default();
+        username_aliases
+            .extend_from_json(r#"{"Łukasz": "Luke", "ΣΊΣΥΦΟΣ": "Sisyphus"}"#)
+            .unwrap();
+        assert_eq!(username_aliases.get("łUKASZ"), "Luke");
+        assert_eq!(username_aliases.get("σίσυφος"), "Sisyphus");
+        assert_eq!(username_aliases.get("Łukas"), "Łukas");
+    }
+
+    #[test]
+    fn test_tls_config() {
+        let cert_path = env::temp_dir().join(format!("psdevbot-cert-{}.pem", process::id()));
+        fs::write(&cert_path, "certificate").unwrap();
+        let missing_path = env::temp_dir().join("psdevbot-missing-key.pem");
+        let tls = TlsConfig::new(
+            Some(cert_path.clone().into()),
+            Some(cert_path.clone().into()),
+        );
+        let error = TlsConfig::new(Some(cert_path.clone().into()), Some(missing_path.into()));
+        let only_cert = TlsConfig::new(Some(cert_path.clone().into()), None);
+        fs::remove_file(&cert_path).unwrap();
+        assert_eq!(tls.unwrap().unwrap().key_path, cert_path);
+        assert!(error
+            .unwrap_err()
+            .starts_with("Couldn't read PSDEVBOT_TLS_KEY"));
+        assert!(only_cert.is_err());
+        assert!(TlsConfig::new(None, None).unwrap().is_none());
+    }
 }
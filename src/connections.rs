@@ -0,0 +1,241 @@
+use crate::config::ServerConfig;
+use crate::unbounded::{MessageSink, Priority, SendError};
+use futures::future;
+use log::info;
+use showdown::SendMessage;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Senders for every configured Showdown server.
+///
+/// The webhook server is shared between connections and outlives them, so a
+/// server's sender is only available while that server is connected.
+pub struct Connections {
+    connections: Vec<Connection>,
+    /// Rooms muted on each server, by the server's index.
+    muted_rooms: Mutex<HashSet<(usize, String)>>,
+}
+
+struct Connection {
+    server: &'static ServerConfig,
+    sender: Mutex<Option<Arc<dyn MessageSink>>>,
+}
+
+impl Connections {
+    pub fn new(servers: &'static [ServerConfig]) -> Self {
+        Self {
+            connections: servers
+                .iter()
+                .map(|server| Connection {
+                    server,
+                    sender: Mutex::new(None),
+                })
+                .collect(),
+            muted_rooms: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn connect(&self, index: usize, sender: Arc<impl MessageSink + 'static>) {
+        *self.connections[index].sender.lock().unwrap() = Some(sender);
+    }
+
+    pub fn disconnect(&self, index: usize) {
+        *self.connections[index].sender.lock().unwrap() = None;
+    }
+
+    /// Shuts down all connected senders, waiting for their queues to drain.
+    pub async fn shutdown(&self, timeout: Duration) {
+        let senders: Vec<_> = self
+            .connections
+            .iter()
+            .filter_map(|connection| connection.sender.lock().unwrap().clone())
+            .collect();
+        future::join_all(senders.iter().map(|sender| sender.shutdown(timeout))).await;
+    }
+
+    /// Stops sending messages to a room on a server, until it's unmuted.
+    pub fn mute(&self, index: usize, room: &str) {
+        self.muted_rooms
+            .lock()
+            .unwrap()
+            .insert((index, room.into()));
+    }
+
+    pub fn unmute(&self, index: usize, room: &str) {
+        self.muted_rooms
+            .lock()
+            .unwrap()
+            .remove(&(index, room.into()));
+    }
+
+    /// Sends a message to every server responsible for `room`.
+    pub async fn send(
+        &self,
+        room: &str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> Result<(), SendError> {
+        self.send_with(room, message, priority, false).await
+    }
+
+    /// Like [`Connections::send`], but waits until the message is actually
+    /// sent to every server instead of only queued.
+    pub async fn send_and_wait(
+        &self,
+        room: &str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> Result<(), SendError> {
+        self.send_with(room, message, priority, true).await
+    }
+
+    async fn send_with(
+        &self,
+        room: &str,
+        message: SendMessage,
+        priority: Priority,
+        wait: bool,
+    ) -> Result<(), SendError> {
+        let mut result = Ok(());
+        let mut deliveries = Vec::new();
+        for (index, connection) in self.connections.iter().enumerate() {
+            if !connection.server.handles_room(room) {
+                continue;
+            }
+            if self
+                .muted_rooms
+                .lock()
+                .unwrap()
+                .contains(&(index, room.into()))
+            {
+                info!(
+                    "Not sending a message to muted room {} on {}",
+                    room, connection.server.server,
+                );
+                continue;
+            }
+            let sender = connection.sender.lock().unwrap().clone();
+            let sent = match sender {
+                Some(sender) if wait => sender
+                    .send_acknowledged(room, message.clone(), priority)
+                    .await
+                    .map(|delivery| deliveries.push(delivery)),
+                Some(sender) => sender.send(room, message.clone(), priority).await,
+                None => Err(SendError::Disconnected),
+            };
+            if sent.is_err() {
+                result = sent;
+            }
+        }
+        for delivered in future::join_all(deliveries).await {
+            if delivered.is_err() {
+                result = delivered;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Connections;
+    use crate::config::ServerConfig;
+    use crate::unbounded::{DelayedSender, Priority, SendError, SenderOptions};
+    use futures::channel::mpsc;
+    use futures::{FutureExt, StreamExt};
+    use showdown::SendMessage;
+    use std::sync::Arc;
+
+    fn server(rooms: &[&str]) -> ServerConfig {
+        ServerConfig {
+            server: "wss://localhost/showdown/websocket".parse().unwrap(),
+            user: "".into(),
+            password: "".into(),
+            assertion: None,
+            rooms: Some(rooms.iter().map(|&room| room.into()).collect()),
+        }
+    }
+
+    fn message(room: &str) -> SendMessage {
+        SendMessage::global_command(format_args!("join {}", room))
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_room() {
+        let servers = Box::leak(Box::new([server(&["a"]), server(&["b"])]));
+        let connections = Connections::new(servers);
+        let (tx_a, mut rx_a) = mpsc::unbounded();
+        let (tx_b, mut rx_b) = mpsc::unbounded();
+        let options = SenderOptions::default();
+        connections.connect(0, Arc::new(DelayedSender::new(tx_a, options)));
+        connections.connect(1, Arc::new(DelayedSender::new(tx_b, options)));
+        connections
+            .send("b", message("b"), Priority::Normal)
+            .await
+            .unwrap();
+        assert_eq!(rx_b.next().await, Some(message("b")));
+        connections
+            .send("a", message("a"), Priority::Normal)
+            .await
+            .unwrap();
+        assert_eq!(rx_a.next().await, Some(message("a")));
+    }
+
+    #[tokio::test]
+    async fn test_send_and_wait() {
+        let servers = Box::leak(Box::new([server(&["a"])]));
+        let connections = Connections::new(servers);
+        let (tx, mut rx) = mpsc::unbounded();
+        let sender = DelayedSender::new(tx, SenderOptions::default());
+        connections.connect(0, Arc::new(sender));
+        connections
+            .send_and_wait("a", message("a"), Priority::Normal)
+            .await
+            .unwrap();
+        assert_eq!(rx.next().now_or_never(), Some(Some(message("a"))));
+    }
+
+    #[tokio::test]
+    async fn test_muted_room() {
+        let servers = Box::leak(Box::new([server(&["a"])]));
+        let connections = Connections::new(servers);
+        connections.mute(0, "a");
+        connections
+            .send("a", message("a"), Priority::Normal)
+            .await
+            .unwrap();
+        connections.unmute(0, "a");
+        assert!(connections
+            .send("a", message("a"), Priority::Normal)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_muted_room_on_other_server() {
+        let servers = Box::leak(Box::new([server(&["a"]), server(&["a"])]));
+        let connections = Connections::new(servers);
+        let (tx, mut rx) = mpsc::unbounded();
+        connections.connect(
+            1,
+            Arc::new(DelayedSender::new(tx, SenderOptions::default())),
+        );
+        connections.mute(0, "a");
+        connections
+            .send("a", message("a"), Priority::Normal)
+            .await
+            .unwrap();
+        assert_eq!(rx.next().await, Some(message("a")));
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_server() {
+        let servers = Box::leak(Box::new([server(&["a"])]));
+        let connections = Connections::new(servers);
+        assert!(matches!(
+            connections.send("a", message("a"), Priority::Normal).await,
+            Err(SendError::Disconnected),
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+use reqwest::Client;
+use serde::Serialize;
+
+/// A Discord webhook that receives a copy of every announcement.
+pub struct DiscordWebhook {
+    url: String,
+    client: Client,
+}
+
+impl DiscordWebhook {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: Client::builder()
+                .user_agent("psdevbot-rust")
+                .build()
+                .unwrap(),
+        }
+    }
+
+    pub async fn send(&self, embed: &Embed) -> reqwest::Result<()> {
+        self.client
+            .post(&self.url)
+            .json(&Payload { embeds: [embed] })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct Payload<'a> {
+    embeds: [&'a Embed; 1],
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Embed {
+    pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+}
+
+impl Embed {
+    /// Creates an embed, taking its color from a hex color like `FF00FF`.
+    pub fn new(description: String, color: &str) -> Self {
+        let color = match color.len() {
+            3 => color.chars().flat_map(|c| [c, c]).collect(),
+            _ => color.to_string(),
+        };
+        Self {
+            description,
+            color: u32::from_str_radix(&color, 16).ok(),
+        }
+    }
+}
+
+/// Escapes characters that Discord would interpret as Markdown.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '~' | '`' | '|' | '[' | ']' | '<' | '>'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A Markdown link, `text` should already be escaped.
+pub fn link(text: &str, url: &str) -> String {
+    format!("[{}]({})", text, url)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, link, Embed};
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("Fix *bold* `code`"), r"Fix \*bold\* \`code\`");
+        assert_eq!(
+            link(&escape("[server]"), "https://github.com/smogon"),
+            r"[\[server\]](https://github.com/smogon)",
+        );
+    }
+
+    #[test]
+    fn test_embed_color() {
+        assert_eq!(Embed::new("".into(), "FF00FF").color, Some(0xFF00FF));
+        assert_eq!(Embed::new("".into(), "f0f").color, Some(0xFF00FF));
+        assert_eq!(Embed::new("".into(), "nope").color, None);
+    }
+}
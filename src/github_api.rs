@@ -1,55 +1,689 @@
-use log::info;
+use crate::circuit_breaker::{CircuitBreaker, CircuitState};
+use futures::{stream, StreamExt};
+use log::{info, warn};
 use lru::LruCache;
-use reqwest::{header, Client};
-use serde::Deserialize;
-use std::time::Duration;
+use reqwest::{header, Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many requests `prefetch_users` makes at once.
+const CONCURRENT_REQUESTS: usize = 4;
+
+/// How long users that don't exist aren't requested again for.
+const MISSING_USER_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How many requests in a row need to fail for the API to not be used for
+/// `CIRCUIT_BREAKER_COOLDOWN`.
+const CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
+const CIRCUIT_BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ClientOptions {
+    /// Timeout for the whole request, including reading the response.
+    pub timeout: Duration,
+    pub connect_timeout: Option<Duration>,
+    /// How many entries each cache of GitHub responses keeps.
+    pub cache_size: usize,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            connect_timeout: None,
+            cache_size: 100,
+        }
+    }
+}
 
 pub struct GitHubApi {
     user: String,
     password: String,
+    base_url: String,
     cache: LruCache<String, User>,
+    cache_file: Option<PathBuf>,
+    repository_cache: LruCache<String, Repository>,
+    /// Commits by `full_name@sha`.
+    commit_cache: LruCache<String, Commit>,
+    /// Users that GitHub recently responded with 404 for, with the time
+    /// until which they aren't requested again.
+    missing_users: LruCache<String, Instant>,
+    missing_user_ttl: Duration,
+    circuit_breaker: Mutex<CircuitBreaker>,
     client: Client,
 }
 
 impl GitHubApi {
-    pub fn new(user: String, password: String) -> Self {
+    pub fn with_base_url(
+        user: String,
+        password: String,
+        base_url: String,
+        options: ClientOptions,
+    ) -> Self {
+        let mut client = Client::builder()
+            .timeout(options.timeout)
+            .user_agent("psdevbot-rust");
+        if let Some(connect_timeout) = options.connect_timeout {
+            client = client.connect_timeout(connect_timeout);
+        }
         Self {
             user,
             password,
-            cache: LruCache::new(100),
-            client: Client::builder()
-                .timeout(Duration::from_secs(5))
-                .user_agent("psdevbot-rust")
-                .build()
-                .unwrap(),
+            base_url: base_url.trim_end_matches('/').into(),
+            cache: LruCache::new(options.cache_size),
+            cache_file: None,
+            repository_cache: LruCache::new(options.cache_size),
+            commit_cache: LruCache::new(options.cache_size),
+            missing_users: LruCache::new(options.cache_size),
+            missing_user_ttl: MISSING_USER_TTL,
+            circuit_breaker: Mutex::new(CircuitBreaker::new(
+                CIRCUIT_BREAKER_THRESHOLD,
+                CIRCUIT_BREAKER_COOLDOWN,
+            )),
+            client: client.build().unwrap(),
+        }
+    }
+
+    /// Loads cached users from `path` if it exists, they are saved there by
+    /// [`Self::save_cache`] on shutdown.
+    pub fn with_cache_file(mut self, path: PathBuf) -> Self {
+        match fs::read(&path) {
+            Ok(contents) => match serde_json::from_slice::<Vec<(String, User)>>(&contents) {
+                // Entries are stored from most to least recently used.
+                Ok(users) => {
+                    for (user_name, user) in users.into_iter().rev() {
+                        self.cache.put(user_name, user);
+                    }
+                }
+                Err(e) => warn!("Ignoring invalid cache file {}: {}", path.display(), e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Couldn't read cache file {}: {}", path.display(), e),
         }
+        self.cache_file = Some(path);
+        self
     }
 
-    pub async fn fetch_user(
+    pub fn save_cache(&self) -> io::Result<()> {
+        if let Some(path) = &self.cache_file {
+            let users: Vec<_> = self.cache.iter().collect();
+            let temporary_path = path.with_extension("tmp");
+            fs::write(&temporary_path, serde_json::to_vec(&users)?)?;
+            fs::rename(temporary_path, path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a user fetched earlier by [`Self::prefetch_users`], without
+    /// making any requests.
+    pub fn cached_user(
         &mut self,
         #[allow(clippy::ptr_arg)] // due to LruCache limitations accepting &String is necessary.
         user_name: &String,
     ) -> Option<&User> {
-        if !self.cache.contains(user_name) {
-            info!("Fetching user `{}` from GitHub", user_name);
-            let user = self
-                .client
-                .get(&format!("https://api.github.com/users/{}", user_name))
-                .header(header::ACCEPT, "application/vnd.github.v3+json")
-                .basic_auth(&self.user, Some(&self.password))
-                .send()
-                .await
-                .ok()?
-                .json()
-                .await
-                .ok()?;
-            self.cache.put(user_name.clone(), user);
-        }
         self.cache.get(user_name)
     }
+
+    /// Fetches uncached users concurrently, so that all users needed for an
+    /// announcement are requested once and without waiting for GitHub one by
+    /// one.
+    pub async fn prefetch_users(&mut self, user_names: impl IntoIterator<Item = String>) {
+        let missing: HashSet<_> = user_names
+            .into_iter()
+            .filter(|user_name| !self.cache.contains(user_name) && !self.is_missing_user(user_name))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let this = &*self;
+        let users: Vec<_> = stream::iter(missing)
+            .map(|user_name| async move {
+                info!("Fetching user `{}` from GitHub", user_name);
+                let user = this.try_get(&format!("users/{}", user_name)).await;
+                (user_name, user)
+            })
+            .buffer_unordered(CONCURRENT_REQUESTS)
+            .collect()
+            .await;
+        for (user_name, user) in users {
+            self.store_user(user_name, user);
+        }
+    }
+
+    /// Returns whether GitHub recently said that this user doesn't exist.
+    #[allow(clippy::ptr_arg)] // due to LruCache limitations accepting &String is necessary.
+    fn is_missing_user(&self, user_name: &String) -> bool {
+        matches!(self.missing_users.peek(user_name), Some(&until) if Instant::now() < until)
+    }
+
+    /// Caches a fetched user. Users that don't exist aren't requested again
+    /// for a while, unlike users that couldn't be fetched due to other
+    /// errors.
+    fn store_user(&mut self, user_name: String, user: Result<Option<User>, RequestError>) {
+        match user {
+            Ok(Some(user)) => {
+                self.cache.put(user_name, user);
+            }
+            Ok(None) => {
+                info!("User `{}` doesn't exist on GitHub", user_name);
+                let until = Instant::now() + self.missing_user_ttl;
+                self.missing_users.put(user_name, until);
+            }
+            Err(RequestError::CircuitOpen) => {}
+            Err(e) => warn!("Couldn't fetch user `{}`: {}", user_name, e),
+        }
+    }
+
+    pub async fn fetch_repository(&mut self, full_name: &str) -> Option<&Repository> {
+        let full_name = full_name.to_string();
+        if !self.repository_cache.contains(&full_name) {
+            info!("Fetching repository `{}` from GitHub", full_name);
+            let repository = self.get(&format!("repos/{}", full_name)).await?;
+            self.repository_cache.put(full_name.clone(), repository);
+        }
+        self.repository_cache.get(&full_name)
+    }
+
+    async fn fetch_commit(&mut self, full_name: &str, sha: &str) -> Option<&Commit> {
+        let key = format!("{}@{}", full_name, sha);
+        if !self.commit_cache.contains(&key) {
+            info!("Fetching commit `{}` from GitHub", key);
+            let commit = self
+                .get(&format!("repos/{}/commits/{}", full_name, sha))
+                .await?;
+            self.commit_cache.put(key.clone(), commit);
+        }
+        self.commit_cache.get(&key)
+    }
+
+    /// Returns the login of a commit author, as GitHub matched it by email.
+    pub async fn fetch_commit_author(&mut self, full_name: &str, sha: &str) -> Option<String> {
+        let commit = self.fetch_commit(full_name, sha).await?;
+        Some(commit.author.as_ref()?.login.clone())
+    }
+
+    /// Returns how many lines a commit added and removed.
+    pub async fn fetch_commit_stats(&mut self, full_name: &str, sha: &str) -> Option<CommitStats> {
+        self.fetch_commit(full_name, sha).await?.stats
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Option<T> {
+        self.try_get(path).await.ok().flatten()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker.lock().unwrap().state()
+    }
+
+    /// Requests `path`, returning `Ok(None)` when GitHub responds with 404.
+    /// Fails without making a request while the circuit breaker is open.
+    async fn try_get<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>, RequestError> {
+        if !self.circuit_breaker.lock().unwrap().allows_request() {
+            return Err(RequestError::CircuitOpen);
+        }
+        let result = self.request(path).await;
+        let mut circuit_breaker = self.circuit_breaker.lock().unwrap();
+        match &result {
+            Ok(_) => circuit_breaker.succeeded(),
+            Err(_) => {
+                if circuit_breaker.failed() {
+                    warn!(
+                        "GitHub API requests keep failing, not using it for {:?}",
+                        CIRCUIT_BREAKER_COOLDOWN,
+                    );
+                }
+            }
+        }
+        result.map_err(RequestError::Http)
+    }
+
+    async fn request<T: DeserializeOwned>(&self, path: &str) -> reqwest::Result<Option<T>> {
+        let response = self
+            .client
+            .get(self.url(path))
+            .header(header::ACCEPT, "application/vnd.github.v3+json")
+            .basic_auth(&self.user, Some(&self.password))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        response.error_for_status()?.json().await.map(Some)
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Debug)]
+enum RequestError {
+    CircuitOpen,
+    Http(reqwest::Error),
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::CircuitOpen => f.write_str("GitHub API circuit breaker is open"),
+            RequestError::Http(e) => e.fmt(f),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct User {
     pub html_url: String,
+    pub avatar_url: String,
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    author: Option<Account>,
+    /// Missing for commits too large for GitHub to diff.
+    #[serde(default)]
+    stats: Option<CommitStats>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct CommitStats {
+    pub additions: u32,
+    pub deletions: u32,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Repository {
+    pub description: Option<String>,
+    pub stargazers_count: u32,
+}
+
+impl Repository {
+    /// One line summary, used as a tooltip for repository names.
+    pub fn summary(&self) -> String {
+        match &self.description {
+            Some(description) => format!("{} ({} stars)", description, self.stargazers_count),
+            None => format!("{} stars", self.stargazers_count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ClientOptions, CommitStats, GitHubApi, User, CIRCUIT_BREAKER_COOLDOWN};
+    use crate::circuit_breaker::CircuitState;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::{env, process};
+    use tokio::time::{self, Instant};
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    impl GitHubApi {
+        /// Fetches a single user, the way announcements do.
+        async fn fetch_user(&mut self, user_name: &String) -> Option<&User> {
+            self.prefetch_users([user_name.clone()]).await;
+            self.cached_user(user_name)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_repository() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("repos" / "smogon" / "pokemon-showdown").map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply::json(&serde_json::json!({
+                "full_name": "smogon/pokemon-showdown",
+                "description": "Pokémon battle simulator.",
+                "stargazers_count": 4000,
+                "default_branch": "master",
+            }))
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        let repository = github_api
+            .fetch_repository("smogon/pokemon-showdown")
+            .await
+            .unwrap();
+        assert_eq!(
+            repository.description.as_deref(),
+            Some("Pokémon battle simulator."),
+        );
+        assert_eq!(repository.stargazers_count, 4000);
+        assert_eq!(
+            repository.summary(),
+            "Pokémon battle simulator. (4000 stars)"
+        );
+        github_api
+            .fetch_repository("smogon/pokemon-showdown")
+            .await
+            .unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(github_api
+            .fetch_repository("smogon/missing")
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_commit_author() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("repos" / "smogon" / "pokemon-showdown" / "commits" / String).map(
+            move |sha: String| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let author = match sha.as_str() {
+                    "0da259" => serde_json::json!({ "login": "xfix" }),
+                    _ => serde_json::Value::Null,
+                };
+                warp::reply::json(&serde_json::json!({ "sha": sha, "author": author }))
+            },
+        );
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                github_api
+                    .fetch_commit_author("smogon/pokemon-showdown", "0da259")
+                    .await
+                    .as_deref(),
+                Some("xfix"),
+            );
+            assert_eq!(
+                github_api
+                    .fetch_commit_author("smogon/pokemon-showdown", "f00ba4")
+                    .await,
+                None,
+            );
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_commit_stats() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("repos" / "smogon" / "pokemon-showdown" / "commits" / String).map(
+            move |sha: String| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let status = match sha.as_str() {
+                    "0da259" => StatusCode::OK,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+                let commit = serde_json::json!({
+                    "sha": sha,
+                    "author": { "login": "xfix" },
+                    "stats": { "total": 52, "additions": 40, "deletions": 12 },
+                });
+                warp::reply::with_status(warp::reply::json(&commit), status)
+            },
+        );
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        let stats = github_api
+            .fetch_commit_stats("smogon/pokemon-showdown", "0da259")
+            .await;
+        assert_eq!(
+            stats,
+            Some(CommitStats {
+                additions: 40,
+                deletions: 12,
+            }),
+        );
+        // The author comes from the cached commit.
+        assert_eq!(
+            github_api
+                .fetch_commit_author("smogon/pokemon-showdown", "0da259")
+                .await
+                .as_deref(),
+            Some("xfix"),
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            github_api
+                .fetch_commit_stats("smogon/pokemon-showdown", "f00ba4")
+                .await,
+            None,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_users() {
+        const DELAY: Duration = Duration::from_millis(200);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("users" / String).and_then(move |user_name: String| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                time::sleep(DELAY).await;
+                Ok::<_, warp::Rejection>(warp::reply::json(&serde_json::json!({
+                    "html_url": format!("https://github.com/{}", user_name),
+                    "avatar_url": "https://avatars.githubusercontent.com/u/1",
+                })))
+            }
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        let user_names: Vec<String> = (0..10).map(|i| format!("author{}", i)).collect();
+        let start = Instant::now();
+        github_api
+            .prefetch_users(user_names.iter().chain(&user_names).cloned())
+            .await;
+        // Fetching the authors one by one would take `10 * DELAY`.
+        assert!(start.elapsed() < 5 * DELAY);
+        for user_name in &user_names {
+            let user = github_api.fetch_user(user_name).await.unwrap();
+            assert_eq!(user.html_url, format!("https://github.com/{}", user_name));
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 10);
+    }
+
+    #[tokio::test]
+    async fn test_missing_user() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("users" / String).map(move |user_name: String| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let status = match user_name.as_str() {
+                "ghost" => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            warp::reply::with_status(warp::reply::json(&serde_json::json!({})), status)
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        for _ in 0..2 {
+            assert!(github_api.fetch_user(&"ghost".into()).await.is_none());
+        }
+        github_api.prefetch_users(vec!["ghost".into()]).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        // Other errors may be temporary, so those requests are retried.
+        for _ in 0..2 {
+            assert!(github_api.fetch_user(&"flaky".into()).await.is_none());
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        github_api.missing_user_ttl = Duration::ZERO;
+        github_api.missing_users.clear();
+        for _ in 0..2 {
+            assert!(github_api.fetch_user(&"ghost".into()).await.is_none());
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("users" / String).map(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let status = StatusCode::INTERNAL_SERVER_ERROR;
+            warp::reply::with_status(warp::reply::json(&serde_json::json!({})), status)
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        for _ in 0..10 {
+            assert!(github_api.fetch_user(&"xfix".into()).await.is_none());
+        }
+        assert_eq!(github_api.circuit_state(), CircuitState::Open);
+        assert_eq!(requests.load(Ordering::SeqCst), 5);
+        time::pause();
+        time::advance(CIRCUIT_BREAKER_COOLDOWN).await;
+        time::resume();
+        assert_eq!(github_api.circuit_state(), CircuitState::HalfOpen);
+        assert!(github_api.fetch_user(&"xfix".into()).await.is_none());
+        assert_eq!(github_api.circuit_state(), CircuitState::Open);
+        assert_eq!(requests.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn test_custom_base_url() {
+        let github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            "https://git.example.com/api/v3/".into(),
+            ClientOptions::default(),
+        );
+        assert_eq!(
+            github_api.url("users/xfix"),
+            "https://git.example.com/api/v3/users/xfix",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let route = warp::path!("users" / "slowpoke").and_then(|| async {
+            time::sleep(Duration::from_secs(10)).await;
+            Ok::<_, warp::Rejection>(warp::reply::json(&serde_json::json!({
+                "html_url": "https://github.com/slowpoke",
+                "avatar_url": "https://avatars.githubusercontent.com/u/1",
+            })))
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions {
+                timeout: Duration::from_millis(100),
+                ..ClientOptions::default()
+            },
+        );
+        let start = Instant::now();
+        assert!(github_api.fetch_user(&"slowpoke".into()).await.is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_cache_size() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("users" / String).map(move |user_name: String| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply::json(&serde_json::json!({
+                "html_url": format!("https://github.com/{}", user_name),
+                "avatar_url": "https://avatars.githubusercontent.com/u/1",
+            }))
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions {
+                cache_size: 2,
+                ..ClientOptions::default()
+            },
+        );
+        for user_name in ["a", "b", "a", "c"] {
+            github_api.fetch_user(&user_name.into()).await.unwrap();
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        // "b" was the least recently used user when "c" was fetched.
+        github_api.fetch_user(&"a".into()).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        github_api.fetch_user(&"b".into()).await.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_cache_file_round_trip() {
+        let path = env::temp_dir().join(format!("psdevbot-cache-{}.json", process::id()));
+        let new_api = || {
+            GitHubApi::with_base_url(
+                "user".into(),
+                "password".into(),
+                "https://api.github.com".into(),
+                ClientOptions::default(),
+            )
+            .with_cache_file(path.clone())
+        };
+        let mut github_api = new_api();
+        for i in 0..=100 {
+            let user = User {
+                html_url: format!("https://github.com/user{}", i),
+                avatar_url: format!("https://avatars.githubusercontent.com/u/{}", i),
+            };
+            github_api.cache.put(format!("user{}", i), user);
+        }
+        github_api.save_cache().unwrap();
+        let mut github_api = new_api();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(github_api.cache.len(), 100);
+        assert!(github_api.cache.get("user0").is_none());
+        let user = github_api.cache.get("user100").unwrap();
+        assert_eq!(user.html_url, "https://github.com/user100");
+        assert_eq!(github_api.cache.peek_lru().unwrap().0, "user1");
+    }
 }
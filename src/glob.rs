@@ -0,0 +1,58 @@
+/// Checks whether `text` matches a glob pattern, in which `*` matches any
+/// sequence of characters and `?` matches a single character.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at, to
+    // backtrack to when the rest of the pattern doesn't match.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::matches;
+
+    #[test]
+    fn test_literal() {
+        assert!(matches("gh-pages", "gh-pages"));
+        assert!(!matches("gh-pages", "gh-pages2"));
+        assert!(!matches("gh-pages", "gh-page"));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches(
+            "dependabot/*",
+            "dependabot/npm_and_yarn/eslint-8.0.0"
+        ));
+        assert!(matches("dependabot/*", "dependabot/"));
+        assert!(!matches("dependabot/*", "dependabot"));
+        assert!(matches("*-wip", "feature-wip"));
+        assert!(matches("release-?", "release-1"));
+        assert!(!matches("release-?", "release-10"));
+        assert!(matches("a*b*c", "axxbyybc"));
+        assert!(!matches("a*b*c", "axxbyyb"));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::commands::is_rank;
+use showdown::message::UpdateUser;
+
+/// Showdown's user ID, which ignores case and anything but letters and
+/// digits.
+fn to_id(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Checks the user update Showdown sends after logging in as `login`.
+pub fn check(update: &UpdateUser<'_>, login: &str) -> Result<(), String> {
+    let mut chars = update.username.chars();
+    let username = match chars.next() {
+        Some(rank) if is_rank(rank) => chars.as_str(),
+        _ => update.username,
+    };
+    if !update.named {
+        return Err(format!(
+            "Showdown didn't accept the login as {}, staying as {}",
+            login, username,
+        ));
+    }
+    if to_id(username) != to_id(login) {
+        return Err(format!("Logged in as {} instead of {}", username, login));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::check;
+    use showdown::message::UpdateUser;
+
+    fn update(username: &str, named: bool) -> UpdateUser<'_> {
+        UpdateUser {
+            username,
+            named,
+            avatar: "1",
+        }
+    }
+
+    #[test]
+    fn test_logged_in() {
+        assert_eq!(check(&update(" PS Dev Bot", true), "psdevbot"), Ok(()));
+    }
+
+    #[test]
+    fn test_guest() {
+        assert_eq!(
+            check(&update(" Guest 123", false), "psdevbot"),
+            Err("Showdown didn't accept the login as psdevbot, staying as Guest 123".into()),
+        );
+    }
+
+    #[test]
+    fn test_other_name() {
+        assert_eq!(
+            check(&update("+PSDevBot2", true), "PSDevBot"),
+            Err("Logged in as PSDevBot2 instead of PSDevBot".into()),
+        );
+    }
+}
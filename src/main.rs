@@ -1,70 +1,409 @@
+mod backoff;
+mod cidr;
+mod circuit_breaker;
+mod commands;
 mod config;
+mod connections;
+mod discord;
 mod github_api;
+mod glob;
+mod login;
+mod rejoin;
+mod request_id;
+mod template_overrides;
 mod unbounded;
 mod webhook;
 
-use config::Config;
+use backoff::Backoff;
+use commands::{parse_command, Command};
+use config::{Config, Credentials, ServerConfig};
+use connections::Connections;
+use futures::channel::oneshot;
 use futures::stream::{SplitStream, StreamExt};
-use log::{error, info};
+use futures::{future, SinkExt};
+use log::{error, info, warn};
+use rejoin::{PendingJoins, Rejoins};
 use showdown::message::{Kind, UpdateUser};
-use showdown::{SendMessage, Stream};
+use showdown::{RoomId, SendMessage, Stream};
+use std::env;
 use std::error::Error;
+use std::future::Future;
+use std::io::Write;
+use std::process;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::time;
-use unbounded::DelayedSender;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{self, Instant};
+use unbounded::{DelayedSender, SenderOptions};
 use webhook::start_server;
 
+const STABLE_CONNECTION_DURATION: Duration = Duration::from_secs(60);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the server has to confirm joining configured rooms.
+const JOIN_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the server has to confirm logging in.
+const LOGIN_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(20);
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     dotenv::dotenv().ok();
+    let check_config = match env::args().nth(1).as_deref() {
+        Some("--check-config") => true,
+        Some(argument) => return Err(format!("Unknown argument {}", argument).into()),
+        None => false,
+    };
     let config = Box::leak(Box::new(Config::new()?));
-    env_logger::init();
+    if check_config {
+        return print_config_summary(config);
+    }
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}{}",
+                buf.timestamp(),
+                buf.default_styled_level(record.level()),
+                record.target(),
+                request_id::log_prefix(),
+                record.args(),
+            )
+        })
+        .init();
+    let connections = Arc::new(Connections::new(&config.servers));
+    let server = start_server(config, Arc::clone(&connections));
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let shutdown = async {
+        tokio::select! {
+            _ = terminate.recv() => info!("Received SIGTERM, shutting down"),
+            _ = interrupt.recv() => info!("Received SIGINT, shutting down"),
+        }
+    };
+    run_until_shutdown(
+        future::join_all(
+            (0..config.servers.len()).map(|index| run_connection(config, &connections, index)),
+        ),
+        shutdown,
+        server,
+        &connections,
+    )
+    .await;
+    save_github_cache(config).await;
+    Ok(())
+}
+
+/// Runs `connections_future` until it finishes or `shutdown` resolves, then
+/// stops the webhook server and sends messages that are still queued.
+async fn run_until_shutdown(
+    connections_future: impl Future,
+    shutdown: impl Future<Output = ()>,
+    server: oneshot::Sender<()>,
+    connections: &Connections,
+) {
+    tokio::select! {
+        _ = connections_future => {}
+        _ = shutdown => {}
+    }
+    server.send(()).ok();
+    connections.shutdown(SHUTDOWN_TIMEOUT).await;
+}
+
+/// Saves users fetched from GitHub to `PSDEVBOT_GITHUB_CACHE_FILE`, if set.
+async fn save_github_cache(config: &Config) {
+    if let Some(github_api) = &config.github_api {
+        if let Err(e) = github_api.lock().await.save_cache() {
+            warn!("Couldn't save the user cache: {}", e);
+        }
+    }
+}
+
+/// Prints what `--check-config` found, exiting with a failure status when
+/// there are problems with the configuration.
+fn print_config_summary(config: &Config) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for server in &config.servers {
+        println!("Server: {} as {}", server.server, server.user);
+    }
+    println!("Port: {}", config.port);
+    println!("Webhook path: /{}", config.webhook_path.join("/"));
+    println!("TLS: {}", if config.tls.is_some() { "on" } else { "off" });
+    println!(
+        "GitHub API: {}",
+        if config.github_api.is_some() {
+            "on"
+        } else {
+            "off"
+        },
+    );
+    println!("{}", serde_json::to_string_pretty(&config.rooms_summary())?);
+    let problems = config.problems();
+    if problems.is_empty() {
+        println!("Configuration is valid");
+        return Ok(());
+    }
+    for problem in problems {
+        println!("Problem: {}", problem);
+    }
+    process::exit(1);
+}
+
+async fn run_connection(config: &'static Config, connections: &Connections, index: usize) {
+    let server = &config.servers[index];
+    let mut backoff = Backoff::new();
     loop {
-        match start(config).await {
-            Ok(()) => info!("Got a regular disconnect"),
+        let connected_at = Instant::now();
+        let result = start(config, server, connections, index).await;
+        connections.disconnect(index);
+        if connected_at.elapsed() >= STABLE_CONNECTION_DURATION {
+            backoff.reset();
+        }
+        match result {
+            Ok(()) => info!("Got a regular disconnect from {}", server.server),
             Err(e) => {
-                error!("Disconnected due to an error: {}", e);
-                time::sleep(Duration::from_secs(10)).await;
+                let delay = backoff.next_delay();
+                error!(
+                    "Disconnected from {} due to an error: {}, reconnecting in {:?}",
+                    server.server, e, delay
+                );
+                time::sleep(delay).await;
             }
         }
     }
 }
 
-async fn start(config: &'static Config) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let stream = time::timeout(Duration::from_secs(30), authenticate(config)).await??;
+async fn start(
+    config: &'static Config,
+    server: &'static ServerConfig,
+    connections: &Connections,
+    index: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let stream = time::timeout(Duration::from_secs(30), authenticate(server)).await??;
     let (sender, receiver) = stream.split();
-    run_authenticated(DelayedSender::new(sender), receiver, config).await
+    let sender = Arc::new(DelayedSender::new(
+        sender,
+        SenderOptions {
+            capacity: config.max_queued_messages,
+            deduplicate: config.deduplicate_messages,
+            per_room: config.per_room_throttle,
+        },
+    ));
+    connections.connect(index, Arc::clone(&sender));
+    run_authenticated(sender, receiver, config, server, connections, index).await
 }
 
-async fn authenticate(config: &'static Config) -> Result<Stream, Box<dyn Error + Send + Sync>> {
-    let mut stream = Stream::connect_to_url(&config.server).await?;
+async fn authenticate(server: &ServerConfig) -> Result<Stream, Box<dyn Error + Send + Sync>> {
+    let mut stream = Stream::connect_to_url(&server.server).await?;
     while let Some(message) = stream.next().await {
         if let Kind::Challenge(ch) = message?.kind() {
-            ch.login_with_password(&mut stream, &config.user, &config.password)
-                .await?;
+            let credentials = server.credentials();
+            match credentials {
+                Credentials::Password(password) => {
+                    ch.login_with_password(&mut stream, &server.user, password)
+                        .await?
+                }
+                Credentials::Assertion(assertion) => {
+                    let command = format!("trn {},0,{}", server.user, assertion);
+                    stream.send(SendMessage::global_command(command)).await?
+                }
+            }
+            time::timeout(
+                LOGIN_CONFIRMATION_TIMEOUT,
+                confirm_login(&mut stream, &server.user),
+            )
+            .await
+            .map_err(|_| {
+                format!(
+                    "Logging in as {} wasn't confirmed in {:?}",
+                    server.user, LOGIN_CONFIRMATION_TIMEOUT,
+                )
+            })?
+            .map_err(|e| match credentials {
+                Credentials::Assertion(_) => {
+                    format!("The assertion for {} was rejected: {}", server.user, e).into()
+                }
+                Credentials::Password(_) => e,
+            })?;
             return Ok(stream);
         }
     }
     Err("Server disconnected before authenticating".into())
 }
 
+/// Waits for the user update following a login, failing if the bot didn't
+/// end up logged in, like when the name is taken.
+async fn confirm_login(
+    stream: &mut Stream,
+    login: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    while let Some(message) = stream.next().await {
+        let message = message?;
+        info!("Received message: {:?}", message);
+        if let Kind::UpdateUser(update) = message.kind() {
+            return Ok(login::check(&update, login)?);
+        }
+    }
+    Err("Server disconnected before confirming the login".into())
+}
+
 async fn run_authenticated(
-    sender: DelayedSender,
+    sender: Arc<DelayedSender>,
     mut receiver: SplitStream<Stream>,
     config: &'static Config,
+    server: &'static ServerConfig,
+    connections: &Connections,
+    index: usize,
 ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-    let sender = Arc::new(sender);
-    let _server = start_server(config, Arc::clone(&sender));
-    while let Some(message) = receiver.next().await {
-        let message = message?;
+    let watchdog = time::sleep(config.watchdog_timeout);
+    tokio::pin!(watchdog);
+    let keepalive_period = config.keepalive_interval.unwrap_or(Duration::from_secs(60));
+    let mut keepalive = time::interval_at(Instant::now() + keepalive_period, keepalive_period);
+    let mut rejoins = Rejoins::default();
+    let mut pending_joins = PendingJoins::default();
+    join_rooms(config, server, &sender, &mut pending_joins).await?;
+    let join_deadline = time::sleep(JOIN_CONFIRMATION_TIMEOUT);
+    tokio::pin!(join_deadline);
+    loop {
+        let message = tokio::select! {
+            message = receiver.next() => match message {
+                Some(message) => message?,
+                None => return Ok(()),
+            },
+            () = &mut watchdog => {
+                return Err(format!(
+                    "No messages received in {:?}, assuming the connection is dead",
+                    config.watchdog_timeout,
+                )
+                .into());
+            }
+            _ = keepalive.tick(), if config.keepalive_interval.is_some() => {
+                if sender.is_idle() {
+                    let command =
+                        SendMessage::global_command(format_args!("cmd userdetails {}", server.user));
+                    sender.send(command).await?;
+                }
+                continue;
+            }
+            () = &mut join_deadline, if !pending_joins.is_empty() => {
+                for room in pending_joins.take_unconfirmed() {
+                    warn!(
+                        "Joining {} wasn't confirmed in {:?}, its notifications may be lost",
+                        room, JOIN_CONFIRMATION_TIMEOUT,
+                    );
+                }
+                continue;
+            }
+        };
+        watchdog
+            .as_mut()
+            .reset(Instant::now() + config.watchdog_timeout);
         info!("Received message: {:?}", message);
-        if let Kind::UpdateUser(UpdateUser { named: true, .. }) = message.kind() {
-            for room in config.all_rooms() {
-                let command = SendMessage::global_command(format_args!("join {}", room));
-                sender.send(command).await?;
+        match message.kind() {
+            Kind::UpdateUser(UpdateUser { named: true, .. }) => {
+                join_rooms(config, server, &sender, &mut pending_joins).await?;
+                join_deadline
+                    .as_mut()
+                    .reset(Instant::now() + JOIN_CONFIRMATION_TIMEOUT);
+            }
+            Kind::NoInit(_) => {
+                let room = message.room().0;
+                // Failed joins are already reported here.
+                pending_joins.remove(room);
+                if config.all_rooms().contains(room) {
+                    warn!("Couldn't join {}", room);
+                    rejoin(&sender, &mut rejoins, room);
+                }
             }
+            _ if rejoin::is_deinit(&message) => {
+                let room = message.room().0;
+                if config.all_rooms().contains(room) {
+                    warn!("Removed from {}", room);
+                    rejoin(&sender, &mut rejoins, room);
+                }
+            }
+            Kind::RoomInit(_) => {
+                let room = message.room().0;
+                rejoins.joined(room);
+                pending_joins.remove(room);
+            }
+            Kind::Chat(chat) => {
+                let room = message.room().0;
+                let reply =
+                    match parse_command(chat.user(), chat.message(), config.min_command_rank) {
+                        Some(Command::Mute) => {
+                            connections.mute(index, room);
+                            "Notifications muted."
+                        }
+                        Some(Command::Unmute) => {
+                            connections.unmute(index, room);
+                            "Notifications unmuted."
+                        }
+                        None => continue,
+                    };
+                sender
+                    .send(SendMessage::chat_message(RoomId(room), reply))
+                    .await?;
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn join_rooms(
+    config: &Config,
+    server: &ServerConfig,
+    sender: &DelayedSender,
+    pending_joins: &mut PendingJoins,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for room in config.all_rooms() {
+        if !server.handles_room(room) {
+            continue;
         }
+        sender.send(join_command(room)).await?;
+        pending_joins.insert(room);
     }
     Ok(())
 }
+
+/// Joins a room again after a delay, unless joining it failed too many
+/// times already.
+fn rejoin(sender: &Arc<DelayedSender>, rejoins: &mut Rejoins, room: &str) {
+    match rejoins.failed(room) {
+        Some(delay) => {
+            info!("Rejoining {} in {:?}", room, delay);
+            let sender = Arc::clone(sender);
+            let command = join_command(room);
+            tokio::spawn(async move {
+                time::sleep(delay).await;
+                sender.send(command).await.ok();
+            });
+        }
+        None => error!("Giving up on joining {}", room),
+    }
+}
+
+fn join_command(room: &str) -> SendMessage {
+    SendMessage::global_command(format_args!("join {}", room))
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_until_shutdown;
+    use crate::connections::Connections;
+    use futures::channel::oneshot;
+    use futures::future;
+
+    #[tokio::test]
+    async fn test_shutdown_stops_server() {
+        let connections = Connections::new(&[]);
+        let (tx, rx) = oneshot::channel();
+        run_until_shutdown(future::pending::<()>(), future::ready(()), tx, &connections).await;
+        assert_eq!(rx.await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_finished_connections_stop_server() {
+        let connections = Connections::new(&[]);
+        let (tx, rx) = oneshot::channel();
+        run_until_shutdown(future::ready(()), future::pending(), tx, &connections).await;
+        assert_eq!(rx.await, Ok(()));
+    }
+}
@@ -0,0 +1,139 @@
+use showdown::message::{Kind, Message};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+const MAX_ATTEMPTS: u32 = 5;
+const BASE_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+pub struct Rejoins {
+    attempts: HashMap<String, u32>,
+}
+
+impl Rejoins {
+    /// Records a failed join and returns how long to wait before trying
+    /// again, or `None` if the bot should give up on the room.
+    pub fn failed(&mut self, room: &str) -> Option<Duration> {
+        let attempts = self.attempts.entry(room.to_string()).or_insert(0);
+        if *attempts >= MAX_ATTEMPTS {
+            return None;
+        }
+        let delay = BASE_DELAY * 2u32.pow(*attempts);
+        *attempts += 1;
+        Some(delay)
+    }
+
+    pub fn joined(&mut self, room: &str) {
+        self.attempts.remove(room);
+    }
+}
+
+/// Returns whether Showdown removed the bot from the message's room, like
+/// after a kick or a room restart.
+pub fn is_deinit(message: &Message) -> bool {
+    // The showdown crate doesn't parse `|deinit`, and exposes unrecognized
+    // messages only through `Debug`.
+    match message.kind() {
+        Kind::Unrecognized(unrecognized) => {
+            format!("{:?}", unrecognized) == r#"UnrecognizedMessage("deinit")"#
+        }
+        _ => false,
+    }
+}
+
+/// Rooms that were joined, but whose joins weren't confirmed by the server
+/// yet.
+#[derive(Debug, Default)]
+pub struct PendingJoins {
+    rooms: HashSet<String>,
+}
+
+impl PendingJoins {
+    pub fn insert(&mut self, room: &str) {
+        self.rooms.insert(room.to_string());
+    }
+
+    pub fn remove(&mut self, room: &str) {
+        self.rooms.remove(room);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    /// Returns sorted rooms that weren't confirmed, forgetting about them.
+    pub fn take_unconfirmed(&mut self) -> Vec<String> {
+        let mut rooms: Vec<_> = self.rooms.drain().collect();
+        rooms.sort();
+        rooms
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_deinit, PendingJoins, Rejoins, BASE_DELAY, MAX_ATTEMPTS};
+    use futures::{SinkExt, StreamExt};
+    use showdown::Stream;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message as WebSocketMessage;
+
+    #[test]
+    fn test_rejoin_gives_up() {
+        let mut rejoins = Rejoins::default();
+        assert_eq!(rejoins.failed("room"), Some(BASE_DELAY));
+        assert_eq!(rejoins.failed("room"), Some(BASE_DELAY * 2));
+        for _ in 2..MAX_ATTEMPTS {
+            assert!(rejoins.failed("room").is_some());
+        }
+        assert_eq!(rejoins.failed("room"), None);
+        assert_eq!(rejoins.failed("other"), Some(BASE_DELAY));
+    }
+
+    #[test]
+    fn test_rejoin_resets_after_join() {
+        let mut rejoins = Rejoins::default();
+        rejoins.failed("room");
+        rejoins.failed("room");
+        rejoins.joined("room");
+        assert_eq!(rejoins.failed("room"), Some(BASE_DELAY));
+    }
+
+    #[test]
+    fn test_pending_joins() {
+        let mut pending_joins = PendingJoins::default();
+        assert!(pending_joins.is_empty());
+        for room in ["techcode", "development", "missing"] {
+            pending_joins.insert(room);
+        }
+        pending_joins.remove("techcode");
+        assert_eq!(pending_joins.take_unconfirmed(), ["development", "missing"]);
+        assert!(pending_joins.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_is_deinit() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(socket).await.unwrap();
+            for message in [
+                ">techcode\n|deinit",
+                ">techcode\n|J|+xfix",
+                ">techcode\n|deinitx",
+            ] {
+                socket
+                    .send(WebSocketMessage::Text(message.into()))
+                    .await
+                    .unwrap();
+            }
+        });
+        let url = format!("ws://{}/", address).parse().unwrap();
+        let mut stream = Stream::connect_to_url(&url).await.unwrap();
+        for expected in [true, false, false] {
+            let message = stream.next().await.unwrap().unwrap();
+            assert_eq!(message.room().0, "techcode");
+            assert_eq!(is_deinit(&message), expected);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+tokio::task_local! {
+    static REQUEST_ID: RequestId;
+}
+
+/// A short random ID of a webhook delivery, included in logs written while
+/// handling it so that concurrent deliveries can be told apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RequestId(u32);
+
+impl RequestId {
+    pub fn new() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        Self(hasher.finish() as u32)
+    }
+
+    /// Runs `future` with this ID as the current one.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        REQUEST_ID.scope(self, future).await
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
+}
+
+/// Prefix for log messages, empty outside of a request.
+pub fn log_prefix() -> String {
+    REQUEST_ID
+        .try_with(|id| format!("[{}] ", id))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{log_prefix, RequestId};
+
+    #[tokio::test]
+    async fn test_log_prefix() {
+        assert_eq!(log_prefix(), "");
+        let id = RequestId::new();
+        let prefix = id.scope(async { log_prefix() }).await;
+        assert_eq!(prefix, format!("[{}] ", id));
+        assert_eq!(prefix.len(), "[01234567] ".len());
+        assert_eq!(log_prefix(), "");
+    }
+
+    #[test]
+    fn test_ids_differ() {
+        assert_ne!(RequestId::new(), RequestId::new());
+    }
+}
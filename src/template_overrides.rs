@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Templates loaded at startup from `PSDEVBOT_TEMPLATE_DIR`, replacing the
+/// compiled in ones with the same file name.
+///
+/// Overrides only support `{{ name }}` placeholders, which are replaced with
+/// already escaped HTML. Unknown placeholders are replaced with nothing.
+#[derive(Debug, Default)]
+pub struct TemplateOverrides {
+    templates: HashMap<String, String>,
+}
+
+impl TemplateOverrides {
+    pub fn load(directory: &Path) -> io::Result<Self> {
+        let mut templates = HashMap::new();
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension() != Some("html".as_ref()) {
+                continue;
+            }
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                let template = fs::read_to_string(&path)?;
+                templates.insert(name.to_string(), template.trim_end().to_string());
+            }
+        }
+        Ok(Self { templates })
+    }
+
+    /// Renders the override for `name`, if there is one.
+    pub fn render(&self, name: &str, values: &[(&str, &str)]) -> Option<String> {
+        let mut template = self.templates.get(name)?.as_str();
+        let mut output = String::with_capacity(template.len());
+        while let Some(start) = template.find("{{") {
+            let end = match template[start..].find("}}") {
+                Some(end) => start + end,
+                None => break,
+            };
+            output += &template[..start];
+            let placeholder = template[start + 2..end].trim();
+            if let Some((_, value)) = values.iter().find(|(name, _)| *name == placeholder) {
+                output += value;
+            }
+            template = &template[end + 2..];
+        }
+        output += template;
+        Some(output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TemplateOverrides;
+    use std::{env, fs, process};
+
+    #[test]
+    fn test_render() {
+        let mut overrides = TemplateOverrides::default();
+        overrides.templates.insert(
+            "star_event.html".into(),
+            "{{repository}} got {{ stars }} stars{{ unknown }} {{ unclosed".into(),
+        );
+        assert_eq!(
+            overrides.render("star_event.html", &[("repository", "[a]"), ("stars", "5")]),
+            Some("[a] got 5 stars {{ unclosed".into()),
+        );
+        assert_eq!(overrides.render("push_event.html", &[]), None);
+    }
+
+    #[test]
+    fn test_load() {
+        let directory = env::temp_dir().join(format!("psdevbot-templates-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("push_event.html"), "{{ commit }}\n").unwrap();
+        fs::write(directory.join("notes.txt"), "Not a template").unwrap();
+        let overrides = TemplateOverrides::load(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+        let overrides = overrides.unwrap();
+        assert_eq!(overrides.render("notes.txt", &[]), None);
+        assert_eq!(
+            overrides.render("push_event.html", &[("commit", "abc")]),
+            Some("abc".into()),
+        );
+    }
+}
@@ -1,39 +1,364 @@
-use futures::channel::mpsc::{self, SendError};
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, PollNext};
 use futures::{Sink, SinkExt};
-use log::info;
+use log::{info, warn};
 use showdown::SendMessage;
-use tokio::time::Duration;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::time::{self, Duration, Instant};
 use tokio_stream::StreamExt;
 
+type Queue = mpsc::UnboundedSender<Queued>;
+
+/// How often a room's queue can send a message.
+const ROOM_DELAY: Duration = Duration::from_millis(700);
+
+/// How often the connection as a whole can send a message, no matter how
+/// many rooms have queued messages. Showdown throttles trusted users to a
+/// message per 100ms per connection, dropping messages once too many are
+/// buffered.
+const CONNECTION_DELAY: Duration = Duration::from_millis(200);
+
+/// A message waiting to be sent, along with whoever waits for it.
+#[derive(Debug)]
+struct Queued {
+    message: SendMessage,
+    sent: Option<oneshot::Sender<()>>,
+}
+
+/// Resolves once a message is actually written to the Showdown connection,
+/// or fails when the connection is gone before that happens.
+#[derive(Debug)]
+pub struct Delivery(Option<oneshot::Receiver<()>>);
+
+impl Delivery {
+    /// A delivery that doesn't need waiting for, like a dropped duplicate of
+    /// an already queued message.
+    pub fn done() -> Self {
+        Self(None)
+    }
+}
+
+impl Future for Delivery {
+    type Output = Result<(), SendError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut self.0 {
+            Some(sent) => Pin::new(sent)
+                .poll(cx)
+                .map(|result| result.map_err(|_| SendError::Disconnected)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// How urgently a message needs to be sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    Normal,
+    /// Sent before already queued normal messages, while still respecting
+    /// the rate limit.
+    High,
+}
+
+/// Queues of a throttled sender, for each priority.
+#[derive(Clone, Debug)]
+struct Lanes {
+    normal: Queue,
+    high: Queue,
+}
+
+impl Lanes {
+    fn get(&self, priority: Priority) -> &Queue {
+        match priority {
+            Priority::Normal => &self.normal,
+            Priority::High => &self.high,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct DelayedSender {
-    sender: mpsc::UnboundedSender<SendMessage>,
+    sender: Lanes,
+    room_senders: Option<Arc<Mutex<HashMap<String, Lanes>>>>,
+    output: Queue,
+    queued: Arc<AtomicUsize>,
+    pending: Option<Arc<Mutex<Vec<SendMessage>>>>,
+    capacity: Option<usize>,
+    closed: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SenderOptions {
+    pub capacity: Option<usize>,
+    pub deduplicate: bool,
+    /// Throttle each room separately, so that a backlog of messages for one
+    /// room doesn't delay messages for other rooms.
+    pub per_room: bool,
 }
 
 impl DelayedSender {
-    pub fn new(mut showdown_sender: impl Sink<SendMessage> + Send + Unpin + 'static) -> Self {
-        let (tx, rx) = mpsc::unbounded::<SendMessage>();
-        let rx = rx.throttle(Duration::from_millis(700));
+    pub fn new(
+        mut showdown_sender: impl Sink<SendMessage> + Send + Unpin + 'static,
+        options: SenderOptions,
+    ) -> Self {
+        let (output, rx) = mpsc::unbounded::<Queued>();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let task_queued = Arc::clone(&queued);
+        let pending = options
+            .deduplicate
+            .then(|| Arc::new(Mutex::new(Vec::new())));
+        let task_pending = pending.clone();
         tokio::spawn(async move {
             tokio::pin!(rx);
-            while let Some(message) = rx.next().await {
+            let mut next_send = Instant::now();
+            while let Some(Queued { message, sent }) = rx.next().await {
+                if Instant::now() < next_send {
+                    time::sleep_until(next_send).await;
+                }
+                if let Some(pending) = &task_pending {
+                    let mut pending = pending.lock().unwrap();
+                    if let Some(index) = pending.iter().position(|m| *m == message) {
+                        pending.remove(index);
+                    }
+                }
                 info!("Sent message: {:?}", message);
                 if showdown_sender.send(message).await.is_err() {
                     return;
                 }
+                next_send = Instant::now() + CONNECTION_DELAY;
+                task_queued.fetch_sub(1, Ordering::SeqCst);
+                if let Some(sent) = sent {
+                    sent.send(()).ok();
+                }
             }
         });
-        Self { sender: tx }
+        Self {
+            sender: throttled(output.clone()),
+            room_senders: options
+                .per_room
+                .then(|| Arc::new(Mutex::new(HashMap::new()))),
+            output,
+            queued,
+            pending,
+            capacity: options.capacity,
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.queued.load(Ordering::SeqCst) == 0
     }
 
     pub async fn send(&self, message: SendMessage) -> Result<(), SendError> {
-        (&self.sender).send(message).await
+        self.enqueue(&self.sender.normal, message, false).map(drop)
+    }
+
+    /// Stops accepting new messages and waits until already queued messages
+    /// are sent, or until `timeout` passes.
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.closed.store(true, Ordering::SeqCst);
+        let drain = async {
+            while !self.is_idle() {
+                time::sleep(Duration::from_millis(50)).await;
+            }
+        };
+        if time::timeout(timeout, drain).await.is_err() {
+            let queued = self.queued.load(Ordering::SeqCst);
+            warn!("Dropping {} queued messages", queued);
+        }
+    }
+
+    /// Sends a message to a room, using a separate queue for that room when
+    /// per-room throttling is enabled.
+    pub async fn send_to_room(
+        &self,
+        room: &str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> Result<(), SendError> {
+        self.enqueue(&self.queue_for(room, priority), message, false)
+            .map(drop)
+    }
+
+    /// Like [`DelayedSender::send_to_room`], but also returns a future
+    /// resolving once the message is actually sent, after throttling.
+    pub async fn send_to_room_acknowledged(
+        &self,
+        room: &str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> Result<Delivery, SendError> {
+        self.enqueue(&self.queue_for(room, priority), message, true)
+    }
+
+    /// Queue for a room, which is separate for each room when per-room
+    /// throttling is enabled.
+    fn queue_for(&self, room: &str, priority: Priority) -> Queue {
+        match &self.room_senders {
+            Some(room_senders) => room_senders
+                .lock()
+                .unwrap()
+                .entry(room.to_string())
+                .or_insert_with(|| throttled(self.output.clone()))
+                .get(priority)
+                .clone(),
+            None => self.sender.get(priority).clone(),
+        }
+    }
+
+    fn enqueue(
+        &self,
+        sender: &Queue,
+        message: SendMessage,
+        acknowledge: bool,
+    ) -> Result<Delivery, SendError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(SendError::ShuttingDown);
+        }
+        // Locked until the message is queued, so that a message that
+        // couldn't be queued isn't treated as pending.
+        let mut pending = self.pending.as_ref().map(|pending| pending.lock().unwrap());
+        if let Some(pending) = &pending {
+            if pending.contains(&message) {
+                info!("Dropped duplicate message: {:?}", message);
+                return Ok(Delivery::done());
+            }
+        }
+        let capacity = self.capacity;
+        self.queued
+            .fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |queued| match capacity {
+                    Some(capacity) if queued >= capacity => None,
+                    _ => Some(queued + 1),
+                },
+            )
+            .map_err(|_| SendError::QueueFull)?;
+        let (sent, delivery) = if acknowledge {
+            let (sent, delivery) = oneshot::channel();
+            (Some(sent), Delivery(Some(delivery)))
+        } else {
+            (None, Delivery::done())
+        };
+        if let Some(pending) = &mut pending {
+            pending.push(message.clone());
+        }
+        let queued = Queued { message, sent };
+        if sender.unbounded_send(queued).is_err() {
+            if let Some(pending) = &mut pending {
+                pending.pop();
+            }
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(SendError::Disconnected);
+        }
+        Ok(delivery)
     }
 }
 
+/// Where messages for a Showdown server end up, so that webhook handlers
+/// don't depend on the throttled sender itself.
+pub trait MessageSink: Send + Sync {
+    fn send<'a>(
+        &'a self,
+        room: &'a str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> BoxFuture<'a, Result<(), SendError>>;
+
+    /// Like [`MessageSink::send`], but also returns a future resolving once
+    /// the message is actually sent.
+    fn send_acknowledged<'a>(
+        &'a self,
+        room: &'a str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> BoxFuture<'a, Result<Delivery, SendError>> {
+        Box::pin(async move {
+            self.send(room, message, priority).await?;
+            Ok(Delivery::done())
+        })
+    }
+
+    /// Waits until already queued messages are sent, or until `timeout`
+    /// passes.
+    fn shutdown(&self, _timeout: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(future::ready(()))
+    }
+}
+
+impl MessageSink for DelayedSender {
+    fn send<'a>(
+        &'a self,
+        room: &'a str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> BoxFuture<'a, Result<(), SendError>> {
+        Box::pin(self.send_to_room(room, message, priority))
+    }
+
+    fn send_acknowledged<'a>(
+        &'a self,
+        room: &'a str,
+        message: SendMessage,
+        priority: Priority,
+    ) -> BoxFuture<'a, Result<Delivery, SendError>> {
+        Box::pin(self.send_to_room_acknowledged(room, message, priority))
+    }
+
+    fn shutdown(&self, timeout: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(DelayedSender::shutdown(self, timeout))
+    }
+}
+
+/// Spawns a task forwarding messages to `output` no more often than Showdown
+/// allows, high priority messages first.
+fn throttled(output: Queue) -> Lanes {
+    let (normal, normal_rx) = mpsc::unbounded();
+    let (high, high_rx) = mpsc::unbounded();
+    tokio::spawn(async move {
+        let mut messages =
+            stream::select_with_strategy(high_rx, normal_rx, |_: &mut ()| PollNext::Left);
+        while let Some(message) = messages.next().await {
+            if output.unbounded_send(message).is_err() {
+                return;
+            }
+            time::sleep(ROOM_DELAY).await;
+        }
+    });
+    Lanes { normal, high }
+}
+
+#[derive(Debug)]
+pub enum SendError {
+    QueueFull,
+    Disconnected,
+    ShuttingDown,
+}
+
+impl Display for SendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::QueueFull => f.write_str("message queue is full"),
+            SendError::Disconnected => f.write_str("message queue is disconnected"),
+            SendError::ShuttingDown => f.write_str("message queue is shutting down"),
+        }
+    }
+}
+
+impl Error for SendError {}
+
 #[cfg(test)]
 mod test {
-    use super::DelayedSender;
+    use super::{DelayedSender, Priority, SendError, SenderOptions, CONNECTION_DELAY, ROOM_DELAY};
     use futures::channel::mpsc;
     use futures::StreamExt;
     use showdown::SendMessage;
@@ -46,7 +371,7 @@ mod test {
         // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
         tokio::spawn(async {
             let (tx, mut rx) = mpsc::unbounded();
-            let sender = DelayedSender::new(tx);
+            let sender = DelayedSender::new(tx, SenderOptions::default());
             let now = Instant::now();
             let message = SendMessage::global_command("test");
             sender.send(message.clone()).await?;
@@ -63,7 +388,7 @@ mod test {
         // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
         tokio::spawn(async {
             let (tx, mut rx) = mpsc::unbounded();
-            let sender = DelayedSender::new(tx);
+            let sender = DelayedSender::new(tx, SenderOptions::default());
             let start = Instant::now();
             let a_message = SendMessage::global_command("a");
             sender.send(a_message.clone()).await?;
@@ -77,4 +402,264 @@ mod test {
         })
         .await?
     }
+
+    #[tokio::test]
+    async fn bounded_sender_rejects_past_capacity() -> Result<(), Box<dyn Error + Send + Sync>> {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, mut rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(
+                tx,
+                SenderOptions {
+                    capacity: Some(2),
+                    ..SenderOptions::default()
+                },
+            );
+            let a_message = SendMessage::global_command("a");
+            sender.send(a_message.clone()).await?;
+            sender.send(SendMessage::global_command("b")).await?;
+            assert!(matches!(
+                sender.send(SendMessage::global_command("c")).await,
+                Err(SendError::QueueFull),
+            ));
+            assert_eq!(rx.next().await, Some(a_message));
+            sender.send(SendMessage::global_command("d")).await?;
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn deduplicating_sender_drops_pending_duplicates(
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, mut rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(
+                tx,
+                SenderOptions {
+                    deduplicate: true,
+                    ..SenderOptions::default()
+                },
+            );
+            let a_message = SendMessage::global_command("a");
+            let b_message = SendMessage::global_command("b");
+            sender.send(a_message.clone()).await?;
+            sender.send(a_message.clone()).await?;
+            sender.send(b_message.clone()).await?;
+            drop(sender);
+            assert_eq!(rx.next().await, Some(a_message));
+            assert_eq!(rx.next().await, Some(b_message));
+            assert_eq!(rx.next().await, None);
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn rejected_message_is_not_treated_as_duplicate(
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, mut rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(
+                tx,
+                SenderOptions {
+                    capacity: Some(1),
+                    deduplicate: true,
+                    ..SenderOptions::default()
+                },
+            );
+            let a_message = SendMessage::global_command("a");
+            let b_message = SendMessage::global_command("b");
+            sender.send(a_message.clone()).await?;
+            assert!(matches!(
+                sender.send(b_message.clone()).await,
+                Err(SendError::QueueFull),
+            ));
+            assert_eq!(rx.next().await, Some(a_message));
+            while !sender.is_idle() {
+                time::sleep(Duration::from_millis(1)).await;
+            }
+            sender.send(b_message.clone()).await?;
+            drop(sender);
+            assert_eq!(rx.next().await, Some(b_message));
+            assert_eq!(rx.next().await, None);
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn per_room_sender_does_not_delay_other_rooms() -> Result<(), Box<dyn Error + Send + Sync>>
+    {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, mut rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(
+                tx,
+                SenderOptions {
+                    per_room: true,
+                    ..SenderOptions::default()
+                },
+            );
+            let start = Instant::now();
+            for message in ["a", "b", "c"] {
+                let message = SendMessage::global_command(message);
+                sender.send_to_room("a", message, Priority::Normal).await?;
+            }
+            let b_message = SendMessage::global_command("d");
+            sender
+                .send_to_room("b", b_message.clone(), Priority::Normal)
+                .await?;
+            let first = rx.next().await;
+            let second = rx.next().await;
+            assert!(first == Some(b_message.clone()) || second == Some(b_message));
+            assert!(Instant::now() < start + ROOM_DELAY);
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn per_room_sender_is_limited_per_connection() -> Result<(), Box<dyn Error + Send + Sync>>
+    {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(
+                tx,
+                SenderOptions {
+                    per_room: true,
+                    ..SenderOptions::default()
+                },
+            );
+            let start = Instant::now();
+            for room in 0..10 {
+                let room = room.to_string();
+                let message = SendMessage::global_command(&room);
+                sender
+                    .send_to_room(&room, message, Priority::Normal)
+                    .await?;
+            }
+            drop(sender);
+            let mut times = Vec::new();
+            rx.for_each(|_| {
+                times.push(Instant::now());
+                async {}
+            })
+            .await;
+            assert_eq!(times.len(), 10);
+            assert_eq!(times[0], start);
+            for pair in times.windows(2) {
+                assert!(pair[1] - pair[0] >= CONNECTION_DELAY);
+            }
+            assert!(times[9] - start < ROOM_DELAY * 9);
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn high_priority_message_jumps_ahead() -> Result<(), Box<dyn Error + Send + Sync>> {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, mut rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(tx, SenderOptions::default());
+            let start = Instant::now();
+            for message in ["a", "b", "c"] {
+                let message = SendMessage::global_command(message);
+                sender.send_to_room("a", message, Priority::Normal).await?;
+            }
+            assert_eq!(rx.next().await, Some(SendMessage::global_command("a")));
+            let urgent = SendMessage::global_command("urgent");
+            sender.send_to_room("a", urgent, Priority::High).await?;
+            drop(sender);
+            let messages: Vec<_> = rx.collect().await;
+            let expected: Vec<_> = ["urgent", "b", "c"]
+                .iter()
+                .map(|&message| SendMessage::global_command(message))
+                .collect();
+            assert_eq!(messages, expected);
+            assert!(Instant::now() >= start + 2 * Duration::from_millis(700));
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn acknowledgment_waits_for_throttling() -> Result<(), Box<dyn Error + Send + Sync>> {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, mut rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(tx, SenderOptions::default());
+            let start = Instant::now();
+            let a_message = SendMessage::global_command("a");
+            sender
+                .send_to_room_acknowledged("a", a_message.clone(), Priority::Normal)
+                .await?
+                .await?;
+            assert_eq!(start, Instant::now());
+            assert_eq!(rx.next().await, Some(a_message));
+            let b_message = SendMessage::global_command("b");
+            sender
+                .send_to_room_acknowledged("a", b_message.clone(), Priority::Normal)
+                .await?
+                .await?;
+            assert!(Instant::now() >= start + Duration::from_millis(700));
+            assert_eq!(rx.next().await, Some(b_message));
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn acknowledgment_fails_when_disconnected() -> Result<(), Box<dyn Error + Send + Sync>> {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(tx, SenderOptions::default());
+            drop(rx);
+            let delivery = sender
+                .send_to_room_acknowledged("a", SendMessage::global_command("a"), Priority::Normal)
+                .await?;
+            assert!(matches!(delivery.await, Err(SendError::Disconnected)));
+            Ok(())
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_queued_messages() -> Result<(), Box<dyn Error + Send + Sync>> {
+        time::pause();
+        // Spawning a task is necessary to workaround https://github.com/tokio-rs/tokio/issues/3108
+        tokio::spawn(async {
+            let (tx, rx) = mpsc::unbounded();
+            let sender = DelayedSender::new(tx, SenderOptions::default());
+            let messages: Vec<_> = ["a", "b", "c"]
+                .iter()
+                .map(|&message| SendMessage::global_command(message))
+                .collect();
+            for message in &messages {
+                sender.send(message.clone()).await?;
+            }
+            sender.shutdown(Duration::from_secs(10)).await;
+            assert!(matches!(
+                sender.send(SendMessage::global_command("d")).await,
+                Err(SendError::ShuttingDown),
+            ));
+            drop(sender);
+            assert_eq!(rx.collect::<Vec<_>>().await, messages);
+            Ok(())
+        })
+        .await?
+    }
 }
@@ -0,0 +1,62 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The last successfully processed webhook of a repository.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LastDelivery {
+    pub event: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Last deliveries by repository full name.
+#[derive(Default)]
+pub struct Deliveries {
+    last: Mutex<HashMap<String, LastDelivery>>,
+}
+
+impl Deliveries {
+    pub fn record(&self, full_name: &str, event: &str, time: SystemTime) {
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let delivery = LastDelivery {
+            event: event.into(),
+            timestamp,
+        };
+        self.last.lock().unwrap().insert(full_name.into(), delivery);
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, LastDelivery> {
+        let last = self.last.lock().unwrap();
+        last.iter()
+            .map(|(name, delivery)| (name.clone(), delivery.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Deliveries, LastDelivery};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_record() {
+        let deliveries = Deliveries::default();
+        let time = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        deliveries.record("smogon/pokemon-showdown", "push", time);
+        deliveries.record("smogon/pokemon-showdown", "pull_request", time);
+        deliveries.record("smogon/pokemon-showdown-client", "push", time);
+        let snapshot = deliveries.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(
+            snapshot["smogon/pokemon-showdown"],
+            LastDelivery {
+                event: "pull_request".into(),
+                timestamp: 1_600_000_000,
+            },
+        );
+    }
+}
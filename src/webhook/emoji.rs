@@ -0,0 +1,117 @@
+//! GitHub emoji shortcodes, like `:sparkles:`, which are common in commit
+//! messages following gitmoji.
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+
+/// Commonly used shortcodes, sorted by name.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "\u{1F44D}"),
+    ("-1", "\u{1F44E}"),
+    ("alembic", "\u{2697}\u{FE0F}"),
+    ("alien", "\u{1F47D}"),
+    ("ambulance", "\u{1F691}"),
+    ("apple", "\u{1F34E}"),
+    ("arrow_down", "\u{2B07}\u{FE0F}"),
+    ("arrow_up", "\u{2B06}\u{FE0F}"),
+    ("art", "\u{1F3A8}"),
+    ("beers", "\u{1F37B}"),
+    ("bento", "\u{1F371}"),
+    ("bookmark", "\u{1F516}"),
+    ("boom", "\u{1F4A5}"),
+    ("bricks", "\u{1F9F1}"),
+    ("bug", "\u{1F41B}"),
+    ("building_construction", "\u{1F3D7}\u{FE0F}"),
+    ("bulb", "\u{1F4A1}"),
+    ("camera_flash", "\u{1F4F8}"),
+    ("card_file_box", "\u{1F5C3}\u{FE0F}"),
+    ("chart_with_upwards_trend", "\u{1F4C8}"),
+    ("check", "\u{2714}\u{FE0F}"),
+    ("children_crossing", "\u{1F6B8}"),
+    ("clown_face", "\u{1F921}"),
+    ("construction", "\u{1F6A7}"),
+    ("construction_worker", "\u{1F477}"),
+    ("dizzy", "\u{1F4AB}"),
+    ("egg", "\u{1F95A}"),
+    ("fire", "\u{1F525}"),
+    ("globe_with_meridians", "\u{1F310}"),
+    ("goal_net", "\u{1F945}"),
+    ("green_heart", "\u{1F49A}"),
+    ("hammer", "\u{1F528}"),
+    ("heart", "\u{2764}\u{FE0F}"),
+    ("heavy_minus_sign", "\u{2796}"),
+    ("heavy_plus_sign", "\u{2795}"),
+    ("iphone", "\u{1F4F1}"),
+    ("label", "\u{1F3F7}\u{FE0F}"),
+    ("lipstick", "\u{1F484}"),
+    ("lock", "\u{1F512}"),
+    ("loud_sound", "\u{1F50A}"),
+    ("mag", "\u{1F50D}"),
+    ("memo", "\u{1F4DD}"),
+    ("monocle_face", "\u{1F9D0}"),
+    ("mute", "\u{1F507}"),
+    ("necktie", "\u{1F454}"),
+    ("package", "\u{1F4E6}"),
+    ("page_facing_up", "\u{1F4C4}"),
+    ("passport_control", "\u{1F6C2}"),
+    ("pencil", "\u{1F4DD}"),
+    ("pencil2", "\u{270F}\u{FE0F}"),
+    ("poop", "\u{1F4A9}"),
+    ("pushpin", "\u{1F4CC}"),
+    ("recycle", "\u{267B}\u{FE0F}"),
+    ("rewind", "\u{23EA}"),
+    ("robot", "\u{1F916}"),
+    ("rocket", "\u{1F680}"),
+    ("rotating_light", "\u{1F6A8}"),
+    ("see_no_evil", "\u{1F648}"),
+    ("seedling", "\u{1F331}"),
+    ("sparkles", "\u{2728}"),
+    ("speech_balloon", "\u{1F4AC}"),
+    ("stethoscope", "\u{1FA7A}"),
+    ("tada", "\u{1F389}"),
+    ("technologist", "\u{1F9D1}\u{200D}\u{1F4BB}"),
+    ("test_tube", "\u{1F9EA}"),
+    ("thread", "\u{1F9F5}"),
+    ("triangular_flag_on_post", "\u{1F6A9}"),
+    ("truck", "\u{1F69A}"),
+    ("twisted_rightwards_arrows", "\u{1F500}"),
+    ("wastebasket", "\u{1F5D1}\u{FE0F}"),
+    ("wheelchair", "\u{267F}"),
+    ("white_check_mark", "\u{2705}"),
+    ("wrench", "\u{1F527}"),
+    ("zap", "\u{26A1}"),
+];
+
+/// Replaces known shortcodes with emoji, leaving unknown ones as they are.
+///
+/// Shortcodes don't contain characters special in HTML, so this can be used
+/// on already escaped text.
+pub fn replace_shortcodes(text: &str) -> Cow<'_, str> {
+    static SHORTCODE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r":([a-z0-9_+-]+):").unwrap());
+    SHORTCODE_PATTERN.replace_all(text, |c: &Captures| {
+        match SHORTCODES.binary_search_by_key(&&c[1], |&(name, _)| name) {
+            Ok(index) => SHORTCODES[index].1.to_string(),
+            Err(_) => c[0].to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{replace_shortcodes, SHORTCODES};
+
+    #[test]
+    fn test_shortcodes_are_sorted() {
+        assert!(SHORTCODES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn test_replace_shortcodes() {
+        assert_eq!(
+            replace_shortcodes(":sparkles: Add :unknown: things :+1:"),
+            "\u{2728} Add :unknown: things \u{1F44D}",
+        );
+        assert_eq!(replace_shortcodes("12:30:45"), "12:30:45");
+    }
+}
@@ -0,0 +1,107 @@
+//! Renders payloads captured in `tests/fixtures` and compares them with the
+//! `.golden.html` files next to them. Run the tests with `UPDATE_GOLDEN=1` to
+//! regenerate the golden files after changing templates.
+
+use super::schema::{PullRequestEvent, PushEvent, PushEventContext, ViewContext};
+use crate::config::{ActionVerbs, CommitTimestamps, UsernameAliases};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn fixture_path(file_name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", file_name]
+        .iter()
+        .collect()
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = fixture_path(&format!("{}.json", name));
+    fs::read(&path).unwrap_or_else(|e| panic!("Couldn't read {}: {}", path.display(), e))
+}
+
+/// Compares `html` with `{name}.golden.html`, or overwrites it when
+/// `UPDATE_GOLDEN` is set.
+fn assert_golden(name: &str, html: &str) {
+    let path = fixture_path(&format!("{}.golden.html", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, format!("{}\n", html)).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "Couldn't read {}: {}, run with UPDATE_GOLDEN=1 to create it",
+            path.display(),
+            e,
+        )
+    });
+    assert_eq!(
+        html,
+        golden.strip_suffix('\n').unwrap_or(&golden),
+        "{} doesn't match, run with UPDATE_GOLDEN=1 if the change is intended",
+        path.display(),
+    );
+}
+
+struct Aliases {
+    username_aliases: UsernameAliases,
+    repository_aliases: HashMap<String, String>,
+    action_verbs: ActionVerbs,
+}
+
+impl Aliases {
+    fn new() -> Self {
+        Self {
+            username_aliases: UsernameAliases::default(),
+            repository_aliases: HashMap::new(),
+            action_verbs: ActionVerbs::default(),
+        }
+    }
+
+    fn view_context(&self) -> ViewContext<'_> {
+        ViewContext {
+            username_aliases: &self.username_aliases,
+            repository_aliases: &self.repository_aliases,
+            action_verbs: &self.action_verbs,
+            showdown_repository_names: true,
+            emoji_shortcodes: false,
+            max_message_length: 120,
+            pull_request_preview_length: 200,
+            show_avatars: true,
+            repository_color: "FF00FF",
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_push() {
+    let input = fixture("push");
+    let push_event: PushEvent = serde_json::from_slice(&input).unwrap();
+    let aliases = Aliases::new();
+    let html = push_event
+        .to_view(PushEventContext {
+            github_api: None,
+            view: aliases.view_context(),
+            hide_merge_commits: false,
+            resolve_commit_authors: false,
+            enrich_authors: true,
+            group_commits: false,
+            commit_stats: false,
+            show_default_branch: false,
+            commit_timestamps: CommitTimestamps::Hidden,
+            gravatars: false,
+            repository_summary: false,
+        })
+        .await
+        .to_string();
+    assert_golden("push", &html);
+}
+
+#[test]
+fn test_pull_request() {
+    let input = fixture("pull_request");
+    let pull_request: PullRequestEvent = serde_json::from_slice(&input).unwrap();
+    let aliases = Aliases::new();
+    let html = pull_request.to_view(aliases.view_context()).to_string();
+    assert_golden("pull_request", &html);
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// An HTML message for a single room.
+pub type RoomMessage = (String, String);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Announcement {
+    Push,
+    Merge,
+}
+
+/// Briefly holds push and pull request merge announcements, so that a merge
+/// and the push it caused can be posted in a room as one message.
+#[derive(Default)]
+pub struct MergeBuffer {
+    pending: Mutex<HashMap<(String, String), Pending>>,
+    next_id: AtomicU64,
+}
+
+struct Pending {
+    id: u64,
+    announcement: Announcement,
+    messages: Vec<RoomMessage>,
+}
+
+pub enum Submitted {
+    /// Messages that should be sent right away.
+    Send(Vec<RoomMessage>),
+    /// The messages were buffered and should be sent using `MergeBuffer::take`
+    /// unless a matching announcement arrives first.
+    Buffered(u64),
+}
+
+impl MergeBuffer {
+    /// Submits messages for a branch (identified by the repository full name
+    /// and branch name).
+    pub fn submit(
+        &self,
+        key: (String, String),
+        announcement: Announcement,
+        messages: Vec<RoomMessage>,
+    ) -> Submitted {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.remove(&key) {
+            Some(other) if other.announcement != announcement => {
+                Submitted::Send(match announcement {
+                    Announcement::Merge => combine(messages, other.messages),
+                    Announcement::Push => combine(other.messages, messages),
+                })
+            }
+            Some(other) => Submitted::Send(other.messages.into_iter().chain(messages).collect()),
+            None => {
+                let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+                pending.insert(
+                    key,
+                    Pending {
+                        id,
+                        announcement,
+                        messages,
+                    },
+                );
+                Submitted::Buffered(id)
+            }
+        }
+    }
+
+    /// Takes buffered messages out, unless they were already combined with
+    /// another announcement.
+    pub fn take(&self, key: &(String, String), id: u64) -> Option<Vec<RoomMessage>> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.get(key)?.id == id {
+            pending.remove(key).map(|pending| pending.messages)
+        } else {
+            None
+        }
+    }
+}
+
+fn combine(first: Vec<RoomMessage>, second: Vec<RoomMessage>) -> Vec<RoomMessage> {
+    let mut combined = first;
+    for (room, html) in second {
+        match combined.iter_mut().find(|(r, _)| *r == room) {
+            Some((_, combined_html)) => {
+                combined_html.push_str("<br>");
+                combined_html.push_str(&html);
+            }
+            None => combined.push((room, html)),
+        }
+    }
+    combined
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Announcement, MergeBuffer, Submitted};
+
+    fn key() -> (String, String) {
+        ("Example/ExampleCom".into(), "master".into())
+    }
+
+    #[test]
+    fn test_push_combined_with_merge() {
+        let buffer = MergeBuffer::default();
+        let push = vec![
+            ("a".into(), "push".into()),
+            ("simple".into(), "simple push".into()),
+        ];
+        let id = match buffer.submit(key(), Announcement::Push, push) {
+            Submitted::Buffered(id) => id,
+            Submitted::Send(_) => panic!("push should be buffered"),
+        };
+        let merge = vec![("a".into(), "merge".into())];
+        match buffer.submit(key(), Announcement::Merge, merge) {
+            Submitted::Send(messages) => assert_eq!(
+                messages,
+                [
+                    ("a".into(), "merge<br>push".into()),
+                    ("simple".into(), "simple push".into()),
+                ],
+            ),
+            Submitted::Buffered(_) => panic!("merge should be combined"),
+        }
+        assert_eq!(buffer.take(&key(), id), None);
+    }
+
+    #[test]
+    fn test_lone_announcement_is_taken() {
+        let buffer = MergeBuffer::default();
+        let merge = vec![("a".into(), "merge".into())];
+        let id = match buffer.submit(key(), Announcement::Merge, merge.clone()) {
+            Submitted::Buffered(id) => id,
+            Submitted::Send(_) => panic!("merge should be buffered"),
+        };
+        assert_eq!(buffer.take(&key(), id), Some(merge));
+        assert_eq!(buffer.take(&key(), id), None);
+    }
+}
@@ -1,181 +1,1016 @@
+mod deliveries;
+mod emoji;
+#[cfg(test)]
+mod golden;
+mod merge_buffer;
+mod recent;
+mod redeliveries;
 mod schema;
+mod strict;
+mod timestamp;
 
-use crate::config::{Config, RoomConfigurationRef, UsernameAliases};
-use crate::unbounded::DelayedSender;
+use crate::config::{Config, RoomCommand, RoomConfigurationRef};
+use crate::connections::Connections;
+use crate::discord::Embed;
+use crate::github_api::GitHubApi;
+use crate::glob;
+use crate::request_id::RequestId;
+use crate::unbounded::{Priority, SendError};
+use deliveries::Deliveries;
 use futures::channel::oneshot;
 use futures::FutureExt;
 use hmac::{Hmac, Mac};
-use log::info;
-use schema::{InitialPayload, PullRequestEvent, PushEvent, PushEventContext};
+use htmlescape::encode_minimal as h;
+use log::{error, info, warn};
+use merge_buffer::{Announcement, MergeBuffer, RoomMessage, Submitted};
+use once_cell::sync::Lazy;
+use recent::RecentPullRequests;
+use redeliveries::HandledDeliveries;
+use regex::Regex;
+use schema::{
+    CheckRunEvent, CommitCommentEvent, DeploymentStatusEvent, DiscussionEvent, ForkEvent,
+    GollumEvent, InitialPayload, IssueCommentEvent, IssuesEvent, MemberEvent, PingEvent,
+    PullRequestEvent, PullRequestReviewEvent, PushEvent, PushEventContext, RepositoryEvent,
+    StarEvent, StatusEvent, ViewContext, WorkflowRunEvent,
+};
 use serde::Deserialize;
 use sha2::Sha256;
 use showdown::{RoomId, SendMessage};
-use std::collections::HashSet;
+use std::any;
+use std::borrow::Cow;
 use std::fmt::{self, Debug, Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::sync::Mutex;
-use std::time::Duration;
+use std::time::SystemTime;
+use tokio::sync::Semaphore;
 use tokio::time;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
 use warp::hyper::body::Bytes;
-use warp::reject::Reject;
-use warp::{path, Filter, Rejection};
+use warp::reject::{PayloadTooLarge, Reject};
+use warp::{path, Filter, Rejection, Reply};
 
-pub fn start_server(config: &'static Config, sender: Arc<DelayedSender>) -> oneshot::Sender<()> {
+pub fn start_server(config: &'static Config, connections: Arc<Connections>) -> oneshot::Sender<()> {
     let (tx, rx) = oneshot::channel();
     let port = config.port;
-    tokio::spawn(
-        warp::serve(get_route(config, sender).with(warp::log("webhook")))
-            .bind_with_graceful_shutdown(([0, 0, 0, 0], port), rx.map(|_| ()))
-            .1,
+    let deliveries = Arc::new(Deliveries::default());
+    let server = warp::serve(
+        get_route(config, connections, Arc::clone(&deliveries))
+            .or(test_route(config))
+            .or(rooms_route(config))
+            .or(deliveries_route(config, deliveries))
+            .or(github_api_route(config))
+            .recover(recover)
+            .with(warp::log("webhook")),
     );
+    let address = ([0, 0, 0, 0], port);
+    let shutdown = rx.map(|_| ());
+    match &config.tls {
+        Some(tls) => tokio::spawn(
+            server
+                .tls()
+                .cert_path(&tls.cert_path)
+                .key_path(&tls.key_path)
+                .bind_with_graceful_shutdown(address, shutdown)
+                .1,
+        ),
+        None => tokio::spawn(server.bind_with_graceful_shutdown(address, shutdown).1),
+    };
     tx
 }
 
 fn get_route(
     config: &'static Config,
-    sender: Arc<DelayedSender>,
-) -> impl Clone + Filter<Extract = (&'static str,), Error = Rejection> {
-    let skip_pull_requests = Arc::new(Mutex::new(HashSet::new()));
-    path!("github" / "callback")
+    connections: Arc<Connections>,
+    deliveries: Arc<Deliveries>,
+) -> impl Clone + Filter<Extract = (impl Reply,), Error = Rejection> {
+    let recent_pull_requests = Arc::new(RecentPullRequests::default());
+    let merge_buffer = Arc::new(MergeBuffer::default());
+    let in_flight = Arc::new(Semaphore::new(config.max_concurrent_webhooks));
+    let handled_deliveries = Arc::new(HandledDeliveries::default());
+    webhook_path(config)
+        .and(allowed_address(config))
         .and(warp::header::optional("X-Hub-Signature-256"))
         .and(warp::header("X-GitHub-Event"))
-        .and(warp::body::bytes())
-        .and_then(move |signature, event: String, bytes: Bytes| {
-            let sender = Arc::clone(&sender);
-            let skip_pull_requests = Arc::clone(&skip_pull_requests);
-            async move {
-                info!("Got event {}", event);
-                let room_configuration = get_rooms(config, signature, &bytes)?;
-                match event.as_str() {
-                    "push" => {
-                        handle_push_event(config, sender, room_configuration, json(&bytes)?).await?
+        .and(warp::header::optional("X-GitHub-Delivery"))
+        .and(body(config))
+        .and_then(
+            move |signature, event: String, delivery: Option<String>, bytes: Bytes| {
+                let connections = Arc::clone(&connections);
+                let recent_pull_requests = Arc::clone(&recent_pull_requests);
+                let merge_buffer = Arc::clone(&merge_buffer);
+                let deliveries = Arc::clone(&deliveries);
+                let handled_deliveries = Arc::clone(&handled_deliveries);
+                let permit = Arc::clone(&in_flight).try_acquire_owned();
+                let request_id = RequestId::new();
+                let handled = async move {
+                    let _permit = permit.map_err(|_| reject(Overloaded))?;
+                    info!("Got event {}", event);
+                    // Only the repository is read here, the payload is parsed again later.
+                    let payload: InitialPayload = serde_json::from_slice(&bytes).map_err(reject)?;
+                    let full_name = &payload.repository.full_name;
+                    let room_configuration = get_rooms(config, full_name, signature, &bytes)?;
+                    // Claimed before handling, so that concurrent redeliveries of a
+                    // webhook that is still being handled are ignored too.
+                    if let Some(delivery) = &delivery {
+                        if !handled_deliveries.insert(delivery) {
+                            info!("Ignoring redelivery of {}", delivery);
+                            return Ok("");
+                        }
+                    }
+                    let result = async {
+                        let ctx = view_context(config, &room_configuration);
+                        let priority = config.event_priority(&event);
+                        let mut response = "";
+                        match event.as_str() {
+                            "ping" => {
+                                let ping: PingEvent = json(config, &bytes)?;
+                                info!(
+                                    "Got ping for hook {} of {}: {}",
+                                    ping.hook_id, full_name, ping.zen
+                                );
+                                response = "pong";
+                            }
+                            "push" => {
+                                handle_push_event(
+                                    config,
+                                    connections,
+                                    merge_buffer,
+                                    room_configuration,
+                                    json(config, &bytes)?,
+                                )
+                                .await?
+                            }
+                            "pull_request" => {
+                                handle_pull_request(
+                                    config,
+                                    ctx,
+                                    recent_pull_requests,
+                                    connections,
+                                    merge_buffer,
+                                    &room_configuration,
+                                    json(config, &bytes)?,
+                                )
+                                .await?
+                            }
+                            "pull_request_review" => {
+                                handle_pull_request_review(
+                                    config,
+                                    ctx,
+                                    connections,
+                                    &room_configuration,
+                                    json(config, &bytes)?,
+                                )
+                                .await?
+                            }
+                            "commit_comment" => {
+                                let comment: CommitCommentEvent = json(config, &bytes)?;
+                                if comment.action == "created" {
+                                    let view = comment.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "issue_comment" => {
+                                let comment: IssueCommentEvent = json(config, &bytes)?;
+                                if comment.action == "created" {
+                                    let view = comment.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "issues" => {
+                                let issue: IssuesEvent = json(config, &bytes)?;
+                                if issue.should_announce() {
+                                    let view = issue.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "star" | "watch" if room_configuration.announce_stars => {
+                                let star: StarEvent = json(config, &bytes)?;
+                                if star.should_announce(room_configuration.star_milestone) {
+                                    let view = star.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "fork" if room_configuration.announce_forks => {
+                                let fork: ForkEvent = json(config, &bytes)?;
+                                let view = fork.to_view(ctx);
+                                forward_to_discord(config, || view.to_discord());
+                                send_html(config, &connections, priority, &room_configuration, view)
+                                    .await?
+                            }
+                            "member" if room_configuration.announce_members => {
+                                let member: MemberEvent = json(config, &bytes)?;
+                                let view = member.to_view(ctx);
+                                forward_to_discord(config, || view.to_discord());
+                                send_html(config, &connections, priority, &room_configuration, view)
+                                    .await?
+                            }
+                            "discussion" if room_configuration.announce_discussions => {
+                                let discussion: DiscussionEvent = json(config, &bytes)?;
+                                if discussion.should_announce() {
+                                    let view = discussion.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "gollum" if room_configuration.announce_wiki => {
+                                let gollum: GollumEvent = json(config, &bytes)?;
+                                if gollum.should_announce() {
+                                    let view = gollum.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "repository" => {
+                                let event: RepositoryEvent = json(config, &bytes)?;
+                                // Push payloads include the current default branch, so
+                                // there is nothing to update after a rename.
+                                if let Some(view) = event.to_default_branch_view(ctx) {
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "deployment_status" => {
+                                let deployment: DeploymentStatusEvent = json(config, &bytes)?;
+                                if deployment
+                                    .should_announce(room_configuration.deployment_environments)
+                                {
+                                    let view = deployment.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "check_run" => {
+                                let check_run: CheckRunEvent = json(config, &bytes)?;
+                                if check_run.should_announce(room_configuration.check_runs) {
+                                    let view = check_run.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "status" => {
+                                let status: StatusEvent = json(config, &bytes)?;
+                                if status.should_announce(
+                                    room_configuration.status_contexts,
+                                    room_configuration.announce_branch,
+                                ) {
+                                    let view = status.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            "workflow_run" => {
+                                let run: WorkflowRunEvent = json(config, &bytes)?;
+                                if run.should_announce(room_configuration.workflows) {
+                                    let view = run.to_view(ctx);
+                                    forward_to_discord(config, || view.to_discord());
+                                    send_html(
+                                        config,
+                                        &connections,
+                                        priority,
+                                        &room_configuration,
+                                        view,
+                                    )
+                                    .await?
+                                }
+                            }
+                            _ => {}
+                        }
+                        if config.is_configured(full_name) {
+                            deliveries.record(full_name, &event, SystemTime::now());
+                        }
+                        Ok::<_, Rejection>(response)
+                    }
+                    .await;
+                    if let (Err(_), Some(delivery)) = (&result, &delivery) {
+                        // Let GitHub retry deliveries that failed.
+                        handled_deliveries.remove(delivery);
+                    }
+                    result
+                };
+                async move {
+                    let reply = match request_id.scope(handled).await {
+                        Ok(response) => response.into_response(),
+                        Err(rejection) => recover(rejection).await?.into_response(),
+                    };
+                    let request_id = request_id.to_string();
+                    Ok::<_, Rejection>(warp::reply::with_header(reply, "X-Request-Id", request_id))
+                }
+            },
+        )
+}
+
+/// Matches `PSDEVBOT_WEBHOOK_PATH`.
+fn webhook_path(config: &'static Config) -> BoxedFilter<()> {
+    path_prefix(&config.webhook_path)
+        .and(warp::path::end())
+        .boxed()
+}
+
+/// Matches `PSDEVBOT_WEBHOOK_PATH` with its last segment replaced by `test`,
+/// which is `/github/test` by default.
+fn signature_test_path(config: &'static Config) -> BoxedFilter<()> {
+    let parent = match config.webhook_path.split_last() {
+        Some((_, parent)) => parent,
+        None => &[],
+    };
+    path_prefix(parent)
+        .and(warp::path("test"))
+        .and(warp::path::end())
+        .boxed()
+}
+
+fn path_prefix(segments: &[String]) -> BoxedFilter<()> {
+    segments
+        .iter()
+        .fold(warp::any().boxed(), |filter, segment| {
+            filter.and(warp::path(segment.clone())).boxed()
+        })
+}
+
+/// Reads the request body, rejecting bodies over `PSDEVBOT_MAX_BODY_BYTES`
+/// before they are read.
+fn body(config: &'static Config) -> impl Clone + Filter<Extract = (Bytes,), Error = Rejection> {
+    warp::body::content_length_limit(config.max_body_bytes).and(warp::body::bytes())
+}
+
+/// Rejects requests from outside of `PSDEVBOT_ALLOWED_CIDRS`, if configured.
+fn allowed_address(
+    config: &'static Config,
+) -> impl Clone + Filter<Extract = (), Error = Rejection> {
+    warp::addr::remote()
+        .and(warp::header::optional("X-Forwarded-For"))
+        .and_then(
+            move |remote: Option<SocketAddr>, forwarded_for: Option<String>| async move {
+                if config.allowed_cidrs.is_empty() {
+                    return Ok(());
+                }
+                let mut address = remote.map(|remote| remote.ip());
+                if config.trust_forwarded_for {
+                    // The last address is the one added by the trusted proxy,
+                    // earlier ones could have been sent by the client.
+                    if let Some(forwarded_for) = forwarded_for {
+                        let last = forwarded_for.rsplit(',').next().unwrap();
+                        address = Some(
+                            last.trim()
+                                .parse()
+                                .map_err(|_| reject(ForbiddenAddress(None)))?,
+                        );
                     }
-                    "pull_request" => {
-                        handle_pull_request(
-                            &config.username_aliases,
-                            skip_pull_requests,
-                            sender,
-                            room_configuration.rooms,
-                            json(&bytes)?,
-                        )
-                        .await?
+                }
+                match address {
+                    Some(address)
+                        if config
+                            .allowed_cidrs
+                            .iter()
+                            .any(|cidr| cidr.contains(address)) =>
+                    {
+                        Ok(())
                     }
-                    _ => {}
+                    _ => Err(reject(ForbiddenAddress(address))),
+                }
+            },
+        )
+        .untuple_one()
+}
+
+struct ForbiddenAddress(Option<IpAddr>);
+
+impl Display for ForbiddenAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(address) => write!(f, "Address {} is not allowed", address),
+            None => write!(f, "Unknown address is not allowed"),
+        }
+    }
+}
+
+/// Rejection for webhooks received while `PSDEVBOT_MAX_CONCURRENT_WEBHOOKS`
+/// are already being handled.
+struct Overloaded;
+
+impl Display for Overloaded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Too many webhooks are being handled, try again later")
+    }
+}
+
+/// Checks a payload's signature the same way `/github/callback` does, but
+/// reports why verification failed instead of posting anything to rooms.
+/// Only available with `PSDEVBOT_DEBUG` enabled.
+fn test_route(
+    config: &'static Config,
+) -> impl Clone + Filter<Extract = (impl Reply,), Error = Rejection> {
+    warp::post()
+        .and(signature_test_path(config))
+        .and(warp::header::optional("X-Hub-Signature-256"))
+        .and(body(config))
+        .and_then(move |signature, bytes: Bytes| async move {
+            if !config.debug {
+                return Err(warp::reject::not_found());
+            }
+            let (reply, status) = match check_signature(config, signature, &bytes) {
+                Ok(()) => (serde_json::json!({ "signature": "valid" }), StatusCode::OK),
+                Err(CheckError::Signature(error)) => (
+                    serde_json::json!({
+                        "signature": "invalid",
+                        "error": error.to_string(),
+                    }),
+                    StatusCode::UNAUTHORIZED,
+                ),
+                Err(CheckError::InvalidPayload(error)) => (
+                    serde_json::json!({ "error": format!("Invalid payload: {}", error) }),
+                    StatusCode::BAD_REQUEST,
+                ),
+                Err(CheckError::UnknownRepository(error)) => (
+                    serde_json::json!({ "error": error.to_string() }),
+                    StatusCode::NOT_FOUND,
+                ),
+            };
+            Ok(warp::reply::with_status(warp::reply::json(&reply), status))
+        })
+}
+
+/// Shows which repositories are announced in which rooms. Only available
+/// with `PSDEVBOT_DEBUG` enabled.
+fn rooms_route(
+    config: &'static Config,
+) -> impl Clone + Filter<Extract = (impl Reply,), Error = Rejection> {
+    warp::get()
+        .and(path!("rooms"))
+        .and_then(move || async move {
+            if config.debug {
+                Ok(warp::reply::json(&config.rooms_summary()))
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+}
+
+/// Shows the last processed event of each configured repository. Only
+/// available with `PSDEVBOT_DEBUG` enabled.
+fn deliveries_route(
+    config: &'static Config,
+    deliveries: Arc<Deliveries>,
+) -> impl Clone + Filter<Extract = (impl Reply,), Error = Rejection> {
+    warp::get().and(path!("deliveries")).and_then(move || {
+        let deliveries = Arc::clone(&deliveries);
+        async move {
+            if config.debug {
+                Ok(warp::reply::json(&deliveries.snapshot()))
+            } else {
+                Err(warp::reject::not_found())
+            }
+        }
+    })
+}
+
+/// Shows whether the GitHub API is used, or skipped due to failing requests.
+/// Only available with `PSDEVBOT_DEBUG` enabled.
+fn github_api_route(
+    config: &'static Config,
+) -> impl Clone + Filter<Extract = (impl Reply,), Error = Rejection> {
+    warp::get()
+        .and(path!("github-api"))
+        .and_then(move || async move {
+            match &config.github_api {
+                Some(github_api) if config.debug => {
+                    let circuit = github_api.lock().await.circuit_state();
+                    Ok(warp::reply::json(
+                        &serde_json::json!({ "circuit": circuit }),
+                    ))
                 }
-                Ok::<_, Rejection>("")
+                _ => Err(warp::reject::not_found()),
             }
         })
 }
 
+/// Why `/github/test` didn't accept a payload.
+enum CheckError {
+    InvalidPayload(serde_json::Error),
+    UnknownRepository(UnknownRepository),
+    Signature(SignatureError),
+}
+
+fn check_signature(
+    config: &Config,
+    signature: Option<String>,
+    bytes: &[u8],
+) -> Result<(), CheckError> {
+    let payload: InitialPayload =
+        serde_json::from_slice(bytes).map_err(CheckError::InvalidPayload)?;
+    let full_name = &payload.repository.full_name;
+    let room_configuration = config
+        .rooms_for(full_name)
+        .ok_or_else(|| CheckError::UnknownRepository(UnknownRepository(full_name.to_string())))?;
+    verify_signature(room_configuration.secrets, signature, bytes).map_err(CheckError::Signature)
+}
+
+fn view_context<'a>(
+    config: &'a Config,
+    room_configuration: &RoomConfigurationRef<'a>,
+) -> ViewContext<'a> {
+    ViewContext {
+        username_aliases: &config.username_aliases,
+        repository_aliases: &config.repository_aliases,
+        action_verbs: &config.action_verbs,
+        showdown_repository_names: config.showdown_repository_names,
+        emoji_shortcodes: config.emoji_shortcodes,
+        max_message_length: config.max_message_length,
+        pull_request_preview_length: config.pull_request_preview_length,
+        show_avatars: room_configuration.show_avatars,
+        repository_color: room_configuration.color,
+    }
+}
+
+fn push_event_context<'a>(
+    config: &'a Config,
+    room_configuration: &RoomConfigurationRef<'a>,
+    github_api: Option<&'a mut GitHubApi>,
+) -> PushEventContext<'a> {
+    PushEventContext {
+        github_api,
+        view: view_context(config, room_configuration),
+        hide_merge_commits: config.hide_merge_commits,
+        resolve_commit_authors: config.resolve_commit_authors,
+        enrich_authors: room_configuration.enrich_authors,
+        group_commits: config.group_commits,
+        commit_stats: config.commit_stats,
+        show_default_branch: config.show_default_branch,
+        commit_timestamps: config.commit_timestamps,
+        gravatars: config.gravatars,
+        repository_summary: config.repository_summaries,
+    }
+}
+
 fn get_rooms<'a>(
     config: &'a Config,
+    full_name: &str,
     signature: Option<String>,
     bytes: &[u8],
 ) -> Result<RoomConfigurationRef<'a>, Rejection> {
-    let payload: InitialPayload = json(bytes)?;
-    let room_configuration = config.rooms_for(&payload.repository.full_name);
-    verify_signature(room_configuration.secret, signature, bytes)?;
+    let room_configuration = config
+        .rooms_for(full_name)
+        .ok_or_else(|| reject(UnknownRepository(full_name.into())))?;
+    verify_signature(room_configuration.secrets, signature, bytes).map_err(reject)?;
     Ok(room_configuration)
 }
 
+/// Rejection for repositories missing from `PSDEVBOT_PROJECT_CONFIGURATION`
+/// when `PSDEVBOT_REQUIRE_KNOWN_REPO` is set.
+struct UnknownRepository(String);
+
+impl Display for UnknownRepository {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Repository {} is not configured", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SignatureError {
+    Missing,
+    WrongPrefix,
+    InvalidHex(hex::FromHexError),
+    Mismatch,
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Missing => write!(f, "Missing X-Hub-Signature-256 header"),
+            SignatureError::WrongPrefix => write!(f, "Signature doesn't start with sha256="),
+            SignatureError::InvalidHex(e) => write!(f, "Signature is not valid hex: {}", e),
+            SignatureError::Mismatch => write!(f, "Signature doesn't match the payload"),
+        }
+    }
+}
+
+/// Checks the payload signature against each secret, accepting the payload
+/// if any of them matches. Empty secrets are ignored, and when there are no
+/// other secrets any payload is accepted.
 fn verify_signature(
-    secret: &str,
+    secrets: &[String],
     signature: Option<String>,
     bytes: &[u8],
-) -> Result<(), Rejection> {
-    if !secret.is_empty() {
-        let signature = signature.ok_or_else(|| reject("Missing signature"))?;
-        let signature = signature
-            .strip_prefix("sha256=")
-            .ok_or_else(|| reject("Signature doesn't start with sha256="))?;
-        let signature = hex::decode(signature).map_err(reject)?;
+) -> Result<(), SignatureError> {
+    let mut secrets = secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .peekable();
+    if secrets.peek().is_none() {
+        return Ok(());
+    }
+    let signature = signature.ok_or(SignatureError::Missing)?;
+    let signature = signature
+        .strip_prefix("sha256=")
+        .ok_or(SignatureError::WrongPrefix)?;
+    let signature = hex::decode(signature).map_err(SignatureError::InvalidHex)?;
+    let matches = secrets.any(|secret| {
         let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
             .expect("HMAC can take a key of any size");
         mac.update(bytes);
-        mac.verify_slice(&signature).map_err(reject)?;
+        mac.verify_slice(&signature).is_ok()
+    });
+    if matches {
+        Ok(())
+    } else {
+        Err(SignatureError::Mismatch)
     }
-    Ok(())
 }
 
-fn json<'de, T: Deserialize<'de>>(input: &'de [u8]) -> Result<T, Rejection> {
-    serde_json::from_slice(input).map_err(reject)
+/// Parses an event payload. With `PSDEVBOT_DEBUG` enabled, top-level fields
+/// that weren't used are logged, to notice when GitHub changes payloads.
+fn json<'de, T: Deserialize<'de>>(config: &Config, input: &'de [u8]) -> Result<T, Rejection> {
+    if !config.debug {
+        return serde_json::from_slice(input).map_err(reject);
+    }
+    let (value, ignored) = strict::from_slice(input).map_err(reject)?;
+    if !ignored.is_empty() {
+        info!(
+            "Ignored fields of {}: {}",
+            any::type_name::<T>(),
+            ignored.join(", "),
+        );
+    }
+    Ok(value)
 }
 
 async fn handle_push_event<'a>(
     config: &'static Config,
-    sender: Arc<DelayedSender>,
+    connections: Arc<Connections>,
+    merge_buffer: Arc<MergeBuffer>,
     room_configuration: RoomConfigurationRef<'a>,
-    push_event: PushEvent<'a>,
+    mut push_event: PushEvent<'a>,
 ) -> Result<(), Rejection> {
-    let mut github_api = match &config.github_api {
-        Some(github_api) => Some(github_api.lock().await),
-        None => None,
+    let priority = config.event_priority("push");
+    if push_event.is_tag() {
+        if push_event.is_release_tag() {
+            let view = push_event.to_tag_view(view_context(config, &room_configuration));
+            forward_to_discord(config, || view.to_discord());
+            send_html(config, &connections, priority, &room_configuration, view).await?;
+        }
+        return Ok(());
+    }
+    if let Some(prefixes) = room_configuration.path_prefixes {
+        push_event.retain_paths(prefixes);
+    }
+    let branch = match push_event.branch() {
+        Some(branch) => branch,
+        None => return Ok(()),
     };
-    if push_event.repository.default_branch == push_event.branch() {
-        for room in room_configuration.rooms {
-            let message = html_command(
-                room,
-                &format!(
-                    "addhtmlbox {}",
-                    push_event
-                        .to_view(PushEventContext {
-                            github_api: github_api.as_deref_mut(),
-                            username_aliases: &config.username_aliases,
-                        })
-                        .await
-                ),
-            );
-            sender.send(message).await.map_err(reject)?;
-        }
-        for room in room_configuration.simple_rooms {
-            let message = html_command(
-                room,
-                &format!(
-                    "addhtmlbox {}",
-                    push_event
-                        .to_simple_view(PushEventContext {
-                            github_api: github_api.as_deref_mut(),
-                            username_aliases: &config.username_aliases,
-                        })
-                        .await
-                ),
-            );
-            sender.send(message).await.map_err(reject)?;
+    let is_announced = match room_configuration.announce_branch {
+        Some(pattern) => glob::matches(pattern, branch),
+        None => branch == push_event.repository.default_branch,
+    };
+    let is_ignored = room_configuration
+        .ignore_branches
+        .iter()
+        .any(|pattern| glob::matches(pattern, branch));
+    if is_announced && !is_ignored && push_event.has_visible_commits(config.hide_merge_commits) {
+        forward_to_discord(config, || {
+            let ctx = view_context(config, &room_configuration);
+            push_event.to_discord(ctx, config.hide_merge_commits)
+        });
+        let mut github_api = match &config.github_api {
+            Some(github_api) => Some(github_api.lock().await),
+            None => None,
+        };
+        // Authors are fetched once, and each variant is rendered once, no
+        // matter how many rooms it's sent to.
+        push_event
+            .prefetch_authors(&mut push_event_context(
+                config,
+                &room_configuration,
+                github_api.as_deref_mut(),
+            ))
+            .await;
+        // Leaves room for the prefix and suffix around the announcement.
+        let max_html_length = config
+            .max_html_length
+            .saturating_sub(wrap_html(&room_configuration, "").len());
+        let mut messages = Vec::new();
+        if !room_configuration.rooms.is_empty() {
+            let ctx = push_event_context(config, &room_configuration, github_api.as_deref_mut());
+            let html = push_event.to_view(ctx).await;
+            let html = html.render_within(&config.template_overrides, max_html_length);
+            let html = wrap_html(&room_configuration, &html);
+            for room in room_configuration.rooms {
+                messages.push((room.clone(), html.clone()));
+            }
         }
+        if !room_configuration.simple_rooms.is_empty() {
+            let ctx = push_event_context(config, &room_configuration, github_api.as_deref_mut());
+            let html = push_event.to_simple_view(ctx).await;
+            let html = html.render_within(&config.template_overrides, max_html_length);
+            let html = wrap_html(&room_configuration, &html);
+            for room in room_configuration.simple_rooms {
+                messages.push((room.clone(), html.clone()));
+            }
+        }
+        // Sending can wait for throttling, which shouldn't block other users
+        // of the GitHub API.
+        drop(github_api);
+        for room in room_configuration.text_rooms {
+            let ctx = view_context(config, &room_configuration);
+            for text in push_event.to_text(ctx, config.hide_merge_commits) {
+                let message = SendMessage::chat_message(RoomId(room), text);
+                send_message(config, &connections, room, message, priority)
+                    .await
+                    .map_err(reject)?;
+            }
+        }
+        let key = (
+            push_event.repository.full_name.to_string(),
+            branch.to_string(),
+        );
+        announce(
+            config,
+            connections,
+            priority,
+            merge_buffer,
+            key,
+            Announcement::Push,
+            messages,
+        )
+        .await?;
     }
     Ok(())
 }
 
 const IGNORE_ACTIONS: &[&str] = &[
-    "ready_for_review",
     "labeled",
     "unlabeled",
     "converted_to_draft",
     "review_request_removed",
 ];
 
+fn is_ignored_action(action: &str, announce_ready_for_review: bool) -> bool {
+    IGNORE_ACTIONS.contains(&action) || (action == "ready_for_review" && !announce_ready_for_review)
+}
+
 async fn handle_pull_request<'a>(
-    username_aliases: &'static UsernameAliases,
-    skip_pull_requests: Arc<Mutex<HashSet<u32>>>,
-    sender: Arc<DelayedSender>,
-    rooms: &'a [String],
+    config: &'static Config,
+    ctx: ViewContext<'a>,
+    recent_pull_requests: Arc<RecentPullRequests>,
+    connections: Arc<Connections>,
+    merge_buffer: Arc<MergeBuffer>,
+    room_configuration: &RoomConfigurationRef<'a>,
     pull_request: PullRequestEvent<'a>,
 ) -> Result<(), Rejection> {
-    let number = pull_request.pull_request.number;
-    if !IGNORE_ACTIONS.contains(&&pull_request.action[..])
-        && skip_pull_requests.lock().unwrap().insert(number)
+    let priority = config.event_priority("pull_request");
+    if !is_ignored_action(&pull_request.action, config.announce_ready_for_review)
+        && !pull_request.is_untitled_edit()
+        && recent_pull_requests.insert(
+            &pull_request.repository.full_name,
+            pull_request.pull_request.number,
+            room_configuration.pull_request_dedup_window,
+        )
     {
+        let view = pull_request.to_view(ctx);
+        forward_to_discord(config, || view.to_discord());
+        let html = view.to_string();
+        send_text(config, &connections, priority, room_configuration, &html).await?;
+        let html = wrap_html(room_configuration, &html);
+        let messages = room_configuration
+            .rooms
+            .iter()
+            .map(|room| (room.clone(), html.clone()))
+            .collect();
+        match pull_request.merged_into() {
+            Some(branch) => {
+                let key = (
+                    pull_request.repository.full_name.to_string(),
+                    branch.to_string(),
+                );
+                announce(
+                    config,
+                    connections,
+                    priority,
+                    merge_buffer,
+                    key,
+                    Announcement::Merge,
+                    messages,
+                )
+                .await?
+            }
+            None => send_messages(config, &connections, priority, messages)
+                .await
+                .map_err(reject)?,
+        }
+    }
+    Ok(())
+}
+
+/// Sends an announcement, first holding it for `PSDEVBOT_MERGE_WINDOW_MS`
+/// (if set) so that a push and the merge that caused it are posted together.
+async fn announce(
+    config: &'static Config,
+    connections: Arc<Connections>,
+    priority: Priority,
+    merge_buffer: Arc<MergeBuffer>,
+    key: (String, String),
+    announcement: Announcement,
+    messages: Vec<RoomMessage>,
+) -> Result<(), Rejection> {
+    let window = match config.merge_window {
+        Some(window) => window,
+        None => {
+            return send_messages(config, &connections, priority, messages)
+                .await
+                .map_err(reject)
+        }
+    };
+    match merge_buffer.submit(key.clone(), announcement, messages) {
+        Submitted::Send(messages) => send_messages(config, &connections, priority, messages)
+            .await
+            .map_err(reject)?,
+        Submitted::Buffered(id) => {
+            tokio::spawn(async move {
+                time::sleep(window).await;
+                if let Some(messages) = merge_buffer.take(&key, id) {
+                    if let Err(e) = send_messages(config, &connections, priority, messages).await {
+                        error!("Couldn't send a buffered announcement: {}", e);
+                    }
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+async fn send_messages(
+    config: &Config,
+    connections: &Connections,
+    priority: Priority,
+    messages: Vec<RoomMessage>,
+) -> Result<(), SendError> {
+    for (room, html) in messages {
+        let message = html_command(config, &room, &html);
+        info!("Queueing an announcement for {}", room);
+        send_message(config, connections, &room, message, priority).await?;
+    }
+    Ok(())
+}
+
+/// Sends a message, waiting until it's actually sent when
+/// `PSDEVBOT_WAIT_FOR_DELIVERY` is set.
+async fn send_message(
+    config: &Config,
+    connections: &Connections,
+    room: &str,
+    message: SendMessage,
+    priority: Priority,
+) -> Result<(), SendError> {
+    if config.wait_for_delivery {
+        connections.send_and_wait(room, message, priority).await
+    } else {
+        connections.send(room, message, priority).await
+    }
+}
+
+async fn handle_pull_request_review<'a>(
+    config: &'static Config,
+    ctx: ViewContext<'a>,
+    connections: Arc<Connections>,
+    room_configuration: &RoomConfigurationRef<'a>,
+    review: PullRequestReviewEvent<'a>,
+) -> Result<(), Rejection> {
+    let priority = config.event_priority("pull_request_review");
+    if review.action != "submitted" {
+        return Ok(());
+    }
+    let has_body = matches!(&review.review.body, Some(body) if !body.is_empty());
+    if review.review.state == "commented" && !has_body {
+        return Ok(());
+    }
+    let view = review.to_view(ctx);
+    forward_to_discord(config, || view.to_discord());
+    send_html(config, &connections, priority, room_configuration, view).await
+}
+
+/// Posts an embed to Discord in the background, if `PSDEVBOT_DISCORD_WEBHOOK`
+/// is set, so that Discord being slow or down doesn't affect Showdown.
+fn forward_to_discord(config: &'static Config, embed: impl FnOnce() -> Embed) {
+    if let Some(discord_webhook) = &config.discord_webhook {
+        let embed = embed();
         tokio::spawn(async move {
-            time::sleep(Duration::from_secs(10 * 60)).await;
-            skip_pull_requests.lock().unwrap().remove(&number);
+            if let Err(e) = discord_webhook.send(&embed).await {
+                warn!("Couldn't forward an announcement to Discord: {}", e);
+            }
         });
-        for room in rooms {
-            let message = html_command(
-                room,
-                &format!("addhtmlbox {}", pull_request.to_view(username_aliases)),
-            );
-            sender.send(message).await.map_err(reject)?;
-        }
+    }
+}
+
+async fn send_html(
+    config: &Config,
+    connections: &Connections,
+    priority: Priority,
+    room_configuration: &RoomConfigurationRef<'_>,
+    html: impl Display,
+) -> Result<(), Rejection> {
+    let html = html.to_string();
+    let wrapped = wrap_html(room_configuration, &html);
+    for room in room_configuration.rooms {
+        let message = html_command(config, room, &wrapped);
+        send_message(config, connections, room, message, priority)
+            .await
+            .map_err(reject)?;
+    }
+    send_text(config, connections, priority, room_configuration, &html).await
+}
+
+/// Sends an announcement as plain text to `text_rooms`, for events that
+/// don't have their own text rendering like pushes do.
+async fn send_text(
+    config: &Config,
+    connections: &Connections,
+    priority: Priority,
+    room_configuration: &RoomConfigurationRef<'_>,
+    html: &str,
+) -> Result<(), Rejection> {
+    if room_configuration.text_rooms.is_empty() {
+        return Ok(());
+    }
+    let text = html_to_text(html);
+    for room in room_configuration.text_rooms {
+        let message = SendMessage::chat_message(RoomId(room), text.clone());
+        send_message(config, connections, room, message, priority)
+            .await
+            .map_err(reject)?;
     }
     Ok(())
 }
@@ -194,7 +1029,1095 @@ impl<T: Display> Debug for ErrorRejection<T> {
 
 impl<T: Display + Send + Sync + 'static> Reject for ErrorRejection<T> {}
 
-fn html_command(room_id: &str, input: &str) -> SendMessage {
-    // Workaround for https://github.com/smogon/pokemon-showdown/pull/7611
-    SendMessage::chat_command(RoomId(room_id), input.replace("here", "her&#101;"))
+async fn recover(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    let (message, status) = if let Some(ErrorRejection(error)) =
+        rejection.find::<ErrorRejection<SignatureError>>()
+    {
+        (error.to_string(), StatusCode::UNAUTHORIZED)
+    } else if let Some(ErrorRejection(error)) =
+        rejection.find::<ErrorRejection<serde_json::Error>>()
+    {
+        (error.to_string(), StatusCode::BAD_REQUEST)
+    } else if let Some(ErrorRejection(error)) = rejection.find::<ErrorRejection<ForbiddenAddress>>()
+    {
+        (error.to_string(), StatusCode::FORBIDDEN)
+    } else if let Some(ErrorRejection(error)) =
+        rejection.find::<ErrorRejection<UnknownRepository>>()
+    {
+        (error.to_string(), StatusCode::NOT_FOUND)
+    } else if let Some(ErrorRejection(error)) = rejection.find::<ErrorRejection<SendError>>() {
+        (error.to_string(), StatusCode::SERVICE_UNAVAILABLE)
+    } else if let Some(ErrorRejection(error)) = rejection.find::<ErrorRejection<Overloaded>>() {
+        (error.to_string(), StatusCode::SERVICE_UNAVAILABLE)
+    } else if rejection.find::<PayloadTooLarge>().is_some() {
+        ("Payload too large".into(), StatusCode::PAYLOAD_TOO_LARGE)
+    } else {
+        return Err(rejection);
+    };
+    Ok(warp::reply::with_status(message, status))
+}
+
+/// Adds the project's `prefix` and `suffix` around an announcement.
+fn wrap_html(room_configuration: &RoomConfigurationRef<'_>, html: &str) -> String {
+    let affix = |text: &str| {
+        if room_configuration.raw_affixes {
+            text.to_string()
+        } else {
+            h(text)
+        }
+    };
+    let mut wrapped = String::new();
+    if let Some(prefix) = room_configuration.prefix {
+        wrapped += &affix(prefix);
+        wrapped.push(' ');
+    }
+    wrapped += html;
+    if let Some(suffix) = room_configuration.suffix {
+        wrapped.push(' ');
+        wrapped += &affix(suffix);
+    }
+    wrapped
+}
+
+/// Wraps an announcement in the command configured for the room in
+/// `PSDEVBOT_ROOM_COMMANDS`.
+fn html_command(config: &Config, room_id: &str, html: &str) -> SendMessage {
+    let command = match config.room_commands.get(room_id) {
+        RoomCommand::HtmlBox => format!("addhtmlbox {}", html),
+        RoomCommand::RankHtmlBox(rank) => format!("addrankhtmlbox {}, {}", rank, html),
+        RoomCommand::Announce => {
+            return SendMessage::chat_command(
+                RoomId(room_id),
+                format!("announce {}", html_to_text(html)),
+            )
+        }
+    };
+    SendMessage::chat_command(
+        RoomId(room_id),
+        escape_here(&command, config.here_workaround),
+    )
+}
+
+/// Flattens an announcement into a single line of plain text.
+fn html_to_text(html: &str) -> String {
+    static TAG_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+    let text = html.replace("<br>", " | ");
+    let text = TAG_PATTERN.replace_all(&text, "");
+    let text = htmlescape::decode_html(&text).unwrap_or_else(|_| text.into_owned());
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Workaround for https://github.com/smogon/pokemon-showdown/pull/7611,
+/// can be disabled with `PSDEVBOT_HERE_WORKAROUND=false` on fixed servers.
+fn escape_here(input: &str, here_workaround: bool) -> Cow<'_, str> {
+    static HERE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?-u:\b)here(?-u:\b)").unwrap());
+    if here_workaround {
+        HERE_PATTERN.replace_all(input, "her&#101;")
+    } else {
+        Cow::Borrowed(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        escape_here, get_route, handle_pull_request, html_command, is_ignored_action, recover,
+        reject, test_route, verify_signature, view_context, wrap_html, ForbiddenAddress,
+        MergeBuffer, RecentPullRequests, SignatureError, UnknownRepository,
+    };
+    use crate::config::{add_test_project, test_config, Config};
+    use crate::connections::Connections;
+    use crate::github_api::{ClientOptions, GitHubApi};
+    use crate::unbounded::{DelayedSender, MessageSink, Priority, SendError, SenderOptions};
+    use futures::channel::{mpsc, oneshot};
+    use futures::future::{self, BoxFuture};
+    use futures::{FutureExt, StreamExt};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+    use showdown::{RoomId, SendMessage};
+    use std::mem;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+    use tokio::task;
+    use warp::http::StatusCode;
+    use warp::{Filter, Rejection, Reply};
+
+    // HMAC-SHA256 of "{}" with the key "secret"
+    const SIGNATURE: &str =
+        "sha256=77325902caca812dc259733aacd046b73817372c777b8d95b402647474516e13";
+
+    #[test]
+    fn test_ignored_actions() {
+        assert!(is_ignored_action("labeled", true));
+        assert!(is_ignored_action("ready_for_review", false));
+        assert!(!is_ignored_action("ready_for_review", true));
+        assert!(!is_ignored_action("opened", false));
+    }
+
+    #[test]
+    fn test_here_workaround() {
+        assert_eq!(
+            escape_here("Click here, not there or <b>here</b>", true),
+            "Click her&#101;, not there or <b>her&#101;</b>",
+        );
+        assert_eq!(
+            escape_here("Adhere to hereafter", true),
+            "Adhere to hereafter"
+        );
+    }
+
+    #[test]
+    fn test_here_workaround_disabled() {
+        assert_eq!(escape_here("Click here", false), "Click here");
+    }
+
+    #[test]
+    fn test_room_commands() {
+        let mut config = test_config(None);
+        config.room_commands = serde_json::from_str(
+            r#"{"staff": "addrankhtmlbox %", "lobby": "announce", "dev": "addhtmlbox"}"#,
+        )
+        .unwrap();
+        let html = "<b>Fix</b> it here &amp; now<br>\n<i>Next</i>";
+        for (room, command) in [
+            (
+                "dev",
+                "addhtmlbox <b>Fix</b> it her&#101; &amp; now<br>\n<i>Next</i>",
+            ),
+            (
+                "other",
+                "addhtmlbox <b>Fix</b> it her&#101; &amp; now<br>\n<i>Next</i>",
+            ),
+            (
+                "staff",
+                "addrankhtmlbox %, <b>Fix</b> it her&#101; &amp; now<br>\n<i>Next</i>",
+            ),
+            ("lobby", "announce Fix it here & now | Next"),
+        ] {
+            assert_eq!(
+                html_command(&config, room, html),
+                SendMessage::chat_command(RoomId(room), command),
+            );
+        }
+    }
+
+    fn secrets(secrets: &[&str]) -> Vec<String> {
+        secrets.iter().map(|&secret| secret.into()).collect()
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        assert_eq!(
+            verify_signature(&secrets(&["secret"]), Some(SIGNATURE.into()), b"{}"),
+            Ok(()),
+        );
+    }
+
+    #[test]
+    fn test_no_secret_accepts_anything() {
+        assert_eq!(verify_signature(&[], None, b"{}"), Ok(()));
+        assert_eq!(verify_signature(&secrets(&[""]), None, b"{}"), Ok(()));
+    }
+
+    #[test]
+    fn test_second_secret_matches() {
+        assert_eq!(
+            verify_signature(&secrets(&["new", "secret"]), Some(SIGNATURE.into()), b"{}"),
+            Ok(()),
+        );
+        assert_eq!(
+            verify_signature(&secrets(&["new", "old"]), Some(SIGNATURE.into()), b"{}"),
+            Err(SignatureError::Mismatch),
+        );
+    }
+
+    #[test]
+    fn test_missing_signature() {
+        assert_eq!(
+            verify_signature(&secrets(&["secret"]), None, b"{}"),
+            Err(SignatureError::Missing),
+        );
+    }
+
+    #[test]
+    fn test_wrong_prefix() {
+        assert_eq!(
+            verify_signature(&secrets(&["secret"]), Some("sha1=00".into()), b"{}"),
+            Err(SignatureError::WrongPrefix),
+        );
+    }
+
+    #[test]
+    fn test_invalid_hex() {
+        assert!(matches!(
+            verify_signature(&secrets(&["secret"]), Some("sha256=xyz".into()), b"{}"),
+            Err(SignatureError::InvalidHex(_)),
+        ));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        assert_eq!(
+            verify_signature(&secrets(&["secret"]), Some(SIGNATURE.into()), b"[]"),
+            Err(SignatureError::Mismatch),
+        );
+    }
+
+    fn pull_request_payload(full_name: &str) -> String {
+        serde_json::json!({
+            "action": "opened",
+            "pull_request": {
+                "number": 5,
+                "html_url": format!("https://github.com/{}/pull/5", full_name),
+                "title": "Fix a bug",
+            },
+            "repository": {
+                "name": full_name.split('/').nth(1).unwrap(),
+                "full_name": full_name,
+                "html_url": format!("https://github.com/{}", full_name),
+                "default_branch": "master",
+            },
+            "sender": { "login": "alice" },
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_same_pull_request_number_in_two_repositories() {
+        let config = Box::leak(Box::new(test_config(Some("room"))));
+        let connections = Arc::new(Connections::new(&config.servers));
+        let (sender, mut receiver) = mpsc::unbounded();
+        let sender = DelayedSender::new(sender, SenderOptions::default());
+        connections.connect(0, Arc::new(sender));
+        let recent_pull_requests = Arc::new(RecentPullRequests::default());
+        let merge_buffer = Arc::new(MergeBuffer::default());
+        let full_names = [
+            "smogon/pokemon-showdown",
+            "smogon/pokemon-showdown",
+            "smogon/pokemon-showdown-client",
+        ];
+        for full_name in full_names {
+            let room_configuration = config.rooms_for(full_name).unwrap();
+            let payload = pull_request_payload(full_name);
+            handle_pull_request(
+                config,
+                view_context(config, &room_configuration),
+                Arc::clone(&recent_pull_requests),
+                Arc::clone(&connections),
+                Arc::clone(&merge_buffer),
+                &room_configuration,
+                serde_json::from_str(&payload).unwrap(),
+            )
+            .await
+            .unwrap();
+        }
+        connections.shutdown(Duration::from_secs(5)).await;
+        let first = receiver.next().await.unwrap();
+        let second = receiver.next().await.unwrap();
+        assert!(format!("{:?}", first).contains("/pokemon-showdown/pull/5"));
+        assert!(format!("{:?}", second).contains("/pokemon-showdown-client/pull/5"));
+        assert!(!matches!(receiver.next().now_or_never(), Some(Some(_))));
+    }
+
+    /// Records messages instead of sending them to Showdown.
+    #[derive(Default)]
+    struct RecordingSink {
+        messages: Mutex<Vec<(String, SendMessage)>>,
+    }
+
+    impl RecordingSink {
+        fn take(&self) -> Vec<(String, SendMessage)> {
+            mem::take(&mut self.messages.lock().unwrap())
+        }
+    }
+
+    impl MessageSink for RecordingSink {
+        fn send<'a>(
+            &'a self,
+            room: &'a str,
+            message: SendMessage,
+            _priority: Priority,
+        ) -> BoxFuture<'a, Result<(), SendError>> {
+            self.messages.lock().unwrap().push((room.into(), message));
+            Box::pin(future::ready(Ok(())))
+        }
+    }
+
+    /// Records messages like [`RecordingSink`], but only finishes sending
+    /// them once the gate has a permit for them.
+    struct GatedSink {
+        recording: RecordingSink,
+        gate: Semaphore,
+    }
+
+    impl MessageSink for GatedSink {
+        fn send<'a>(
+            &'a self,
+            room: &'a str,
+            message: SendMessage,
+            priority: Priority,
+        ) -> BoxFuture<'a, Result<(), SendError>> {
+            Box::pin(async move {
+                self.recording.send(room, message, priority).await?;
+                self.gate.acquire().await.unwrap().forget();
+                Ok(())
+            })
+        }
+    }
+
+    /// Builds the `/github/callback` route for a single server connection
+    /// sending messages to `sink`.
+    fn route_with_sink(
+        config: Config,
+        sink: Arc<impl MessageSink + 'static>,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        let config = Box::leak(Box::new(config));
+        let connections = Arc::new(Connections::new(&config.servers));
+        connections.connect(0, sink);
+        get_route(config, connections, Arc::default()).recover(recover)
+    }
+
+    /// Builds the `/github/callback` route for a single server connection,
+    /// returning the sink recording messages sent to that server.
+    fn callback_route(
+        config: Config,
+    ) -> (
+        impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone,
+        Arc<RecordingSink>,
+    ) {
+        let sink = Arc::new(RecordingSink::default());
+        (route_with_sink(config, Arc::clone(&sink)), sink)
+    }
+
+    fn sign(secret: &str, body: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body.as_bytes());
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn push_payload() -> String {
+        serde_json::json!({
+            "ref": "refs/heads/master",
+            "before": "6113728f27ae82c7b1a177c8d03f9e96e0adf246",
+            "after": "0da2590a700d054fc2ce39ddc9c95f360329d9be",
+            "created": false,
+            "deleted": false,
+            "forced": false,
+            "commits": [{
+                "id": "0da2590a700d054fc2ce39ddc9c95f360329d9be",
+                "tree_id": "f9d2a07e9488b91af2641b26b9407fe22a451433",
+                "distinct": true,
+                "message": "Fix Struggle recoil",
+                "timestamp": "2021-05-01T12:00:00+02:00",
+                "url": "https://github.com/smogon/pokemon-showdown/commit/0da2590a700d054fc2ce39ddc9c95f360329d9be",
+                "author": {
+                    "name": "Konrad Borowski",
+                    "email": "konrad@borowski.pw",
+                    "username": "xfix",
+                },
+                "committer": {
+                    "name": "GitHub",
+                    "email": "noreply@github.com",
+                    "username": "web-flow",
+                },
+                "added": [],
+                "removed": [],
+                "modified": ["data/moves.ts"],
+            }],
+            "repository": {
+                "id": 3_171_710,
+                "name": "pokemon-showdown",
+                "full_name": "smogon/pokemon-showdown",
+                "html_url": "https://github.com/smogon/pokemon-showdown",
+                "default_branch": "master",
+                "stargazers_count": 4000,
+            },
+            "pusher": { "name": "xfix", "email": "konrad@borowski.pw" },
+            "sender": { "login": "xfix", "id": 1_297_598 },
+        })
+        .to_string()
+    }
+
+    fn signed_config() -> Config {
+        let mut config = test_config(Some("room"));
+        config.secrets = vec!["secret".into()];
+        config
+    }
+
+    #[tokio::test]
+    async fn test_push_route() {
+        let (route, sink) = callback_route(signed_config());
+        let payload = push_payload();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "push")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request_id = response.headers()["X-Request-Id"].to_str().unwrap();
+        assert!(request_id.chars().all(|c| c.is_ascii_hexdigit()));
+        let html = concat!(
+            "addhtmlbox [<a href='https://github.com/smogon/pokemon-showdown'>",
+            "<font color=FF00FF>server</font></a>] ",
+            "<a href='https://github.com/smogon/pokemon-showdown/commit/",
+            "0da2590a700d054fc2ce39ddc9c95f360329d9be'>",
+            "<font color=606060><kbd>0da259</kbd></font></a>\n",
+            "<span title='Fix Struggle recoil'>Fix Struggle recoil</span> ",
+            r#"<font color=909090 title="Konrad Borowski">(xfix)</font>"#,
+        );
+        assert_eq!(
+            sink.take(),
+            [(
+                "room".into(),
+                SendMessage::chat_command(RoomId("room"), html)
+            )],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_after_default_branch_rename() {
+        let (route, sink) = callback_route(signed_config());
+        let rename = serde_json::json!({
+            "action": "edited",
+            "changes": { "default_branch": { "from": "master" } },
+            "repository": {
+                "name": "pokemon-showdown",
+                "full_name": "smogon/pokemon-showdown",
+                "html_url": "https://github.com/smogon/pokemon-showdown",
+                "default_branch": "main",
+            },
+            "sender": { "login": "xfix" },
+        })
+        .to_string();
+        let mut push: serde_json::Value = serde_json::from_str(&push_payload()).unwrap();
+        push["repository"]["default_branch"] = "main".into();
+        let mut old_branch_push = push.clone();
+        push["ref"] = "refs/heads/main".into();
+        old_branch_push["ref"] = "refs/heads/master".into();
+        for (event, payload) in [
+            ("repository", rename),
+            ("push", push.to_string()),
+            ("push", old_branch_push.to_string()),
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/github/callback")
+                .header("X-GitHub-Event", event)
+                .header("X-Hub-Signature-256", sign("secret", &payload))
+                .body(&payload)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let messages: Vec<_> = sink
+            .take()
+            .into_iter()
+            .map(|(_, message)| format!("{:?}", message))
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("changed the default branch"));
+        assert!(messages[1].contains("Fix Struggle recoil"));
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_in_text_room() {
+        let mut config = signed_config();
+        add_test_project(
+            &mut config,
+            "smogon/pokemon-showdown",
+            r#"{"text_rooms": ["text"]}"#,
+        );
+        let (route, sink) = callback_route(config);
+        let payload = pull_request_payload("smogon/pokemon-showdown");
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "pull_request")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            sink.take(),
+            [(
+                "text".into(),
+                SendMessage::chat_message(RoomId("text"), "[server] alice opened PR#5: Fix a bug")
+            )],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefix_and_suffix() {
+        let mut config = signed_config();
+        add_test_project(
+            &mut config,
+            "smogon/pokemon-showdown",
+            r#"{"rooms": ["room"], "prefix": "<PS>", "suffix": "& more"}"#,
+        );
+        let (route, sink) = callback_route(config);
+        let payload = push_payload();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "push")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let html = concat!(
+            "addhtmlbox &lt;PS&gt; [<a href='https://github.com/smogon/pokemon-showdown'>",
+            "<font color=FF00FF>server</font></a>] ",
+            "<a href='https://github.com/smogon/pokemon-showdown/commit/",
+            "0da2590a700d054fc2ce39ddc9c95f360329d9be'>",
+            "<font color=606060><kbd>0da259</kbd></font></a>\n",
+            "<span title='Fix Struggle recoil'>Fix Struggle recoil</span> ",
+            r#"<font color=909090 title="Konrad Borowski">(xfix)</font> &amp; more"#,
+        );
+        assert_eq!(
+            sink.take(),
+            [(
+                "room".into(),
+                SendMessage::chat_command(RoomId("room"), html)
+            )],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prefix_fits_within_max_html_length() {
+        let mut config = signed_config();
+        config.max_html_length = 2000;
+        let prefix = "P".repeat(500);
+        let json = serde_json::json!({ "rooms": ["room"], "prefix": prefix });
+        add_test_project(&mut config, "smogon/pokemon-showdown", &json.to_string());
+        let (route, sink) = callback_route(config);
+        let mut payload: serde_json::Value = serde_json::from_str(&push_payload()).unwrap();
+        let commit = payload["commits"][0].clone();
+        payload["commits"] = vec![commit; 20].into();
+        let payload = payload.to_string();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "push")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let messages = sink.take();
+        // The command is only exposed through `Debug`, whose escapes are
+        // valid JSON here.
+        let debug = format!("{:?}", messages[0].1);
+        let debug = debug.strip_prefix("SendMessage(").unwrap();
+        let command: String = serde_json::from_str(debug.strip_suffix(')').unwrap()).unwrap();
+        let html = command.strip_prefix("room|/addhtmlbox ").unwrap();
+        assert!(html.starts_with(&prefix));
+        assert!(html.ends_with("&hellip;and more"), "{}", html);
+        assert!(html.len() <= 2000, "{}", html.len());
+    }
+
+    #[test]
+    fn test_raw_affixes() {
+        let mut config = test_config(None);
+        add_test_project(
+            &mut config,
+            "project",
+            r#"{"prefix": "<b>PS</b>", "suffix": "<hr>", "raw_affixes": true}"#,
+        );
+        let room_configuration = config.rooms_for("project").unwrap();
+        assert_eq!(
+            wrap_html(&room_configuration, "announcement"),
+            "<b>PS</b> announcement <hr>",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authors_fetched_once_per_push() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        // Failed requests aren't cached, so a second fetch would be visible.
+        let github = warp::path!("users" / String).map(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let status = StatusCode::INTERNAL_SERVER_ERROR;
+            warp::reply::with_status(warp::reply::json(&serde_json::json!({})), status)
+        });
+        let (address, server) = warp::serve(github).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut config = signed_config();
+        config.github_api = Some(futures::lock::Mutex::new(GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        )));
+        add_test_project(
+            &mut config,
+            "smogon/pokemon-showdown",
+            r#"{"rooms": ["a", "b"], "simple_rooms": ["c", "d"]}"#,
+        );
+        let (route, sink) = callback_route(config);
+        let payload = push_payload();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "push")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(sink.take().len(), 4);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_redelivery_is_ignored() {
+        let (route, sink) = callback_route(signed_config());
+        let payload = push_payload();
+        for (delivery, announced) in [("first", true), ("first", false), ("second", true)] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/github/callback")
+                .header("X-GitHub-Event", "push")
+                .header("X-GitHub-Delivery", delivery)
+                .header("X-Hub-Signature-256", sign("secret", &payload))
+                .body(&payload)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(sink.take().len(), announced as usize, "{}", delivery);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_redelivery_is_ignored() {
+        let sink = Arc::new(GatedSink {
+            recording: RecordingSink::default(),
+            gate: Semaphore::new(0),
+        });
+        let route = route_with_sink(signed_config(), Arc::clone(&sink));
+        let payload = push_payload();
+        let deliver = || {
+            let route = route.clone();
+            let payload = payload.clone();
+            tokio::spawn(async move {
+                warp::test::request()
+                    .method("POST")
+                    .path("/github/callback")
+                    .header("X-GitHub-Event", "push")
+                    .header("X-GitHub-Delivery", "first")
+                    .header("X-Hub-Signature-256", sign("secret", &payload))
+                    .body(&payload)
+                    .reply(&route)
+                    .await
+                    .status()
+            })
+        };
+        let first = deliver();
+        // Wait until the first delivery is sending its announcement.
+        while sink.recording.messages.lock().unwrap().is_empty() {
+            task::yield_now().await;
+        }
+        let second = deliver();
+        sink.gate.add_permits(2);
+        assert_eq!(first.await.unwrap(), StatusCode::OK);
+        assert_eq!(second.await.unwrap(), StatusCode::OK);
+        assert_eq!(sink.recording.take().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried() {
+        let (route, sink) = callback_route(signed_config());
+        let payload = push_payload();
+        // Has the repository, but isn't a valid push.
+        let mut invalid: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        invalid["commits"] = "none".into();
+        let invalid = invalid.to_string();
+        for (body, status, announced) in [
+            (&invalid, StatusCode::BAD_REQUEST, 0),
+            (&payload, StatusCode::OK, 1),
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/github/callback")
+                .header("X-GitHub-Event", "push")
+                .header("X-GitHub-Delivery", "first")
+                .header("X-Hub-Signature-256", sign("secret", body))
+                .body(body)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), status);
+            assert_eq!(sink.take().len(), announced);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_announce_branch() {
+        let mut config = signed_config();
+        add_test_project(
+            &mut config,
+            "smogon/pokemon-showdown",
+            r#"{"rooms": ["room"], "announce_branch": "production"}"#,
+        );
+        let (route, sink) = callback_route(config);
+        for (branch, announced) in [("master", false), ("production", true)] {
+            let payload =
+                push_payload().replace("refs/heads/master", &format!("refs/heads/{}", branch));
+            let response = warp::test::request()
+                .method("POST")
+                .path("/github/callback")
+                .header("X-GitHub-Event", "push")
+                .header("X-Hub-Signature-256", sign("secret", &payload))
+                .body(&payload)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(sink.take().len(), announced as usize, "{}", branch);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ignore_branches() {
+        let mut config = signed_config();
+        add_test_project(
+            &mut config,
+            "smogon/pokemon-showdown",
+            r#"{
+                "rooms": ["room"],
+                "announce_branch": "*",
+                "ignore_branches": ["gh-pages", "dependabot/*"]
+            }"#,
+        );
+        let (route, sink) = callback_route(config);
+        for (branch, announced) in [
+            ("master", true),
+            ("feature/teams", true),
+            ("dependabot/npm_and_yarn/eslint-8.0.0", false),
+            ("gh-pages", false),
+        ] {
+            let payload =
+                push_payload().replace("refs/heads/master", &format!("refs/heads/{}", branch));
+            let response = warp::test::request()
+                .method("POST")
+                .path("/github/callback")
+                .header("X-GitHub-Event", "push")
+                .header("X-Hub-Signature-256", sign("secret", &payload))
+                .body(&payload)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(sink.take().len(), announced as usize, "{}", branch);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhook_path() {
+        let mut config = signed_config();
+        config.webhook_path = vec!["hooks".into(), "github".into()];
+        let (route, sink) = callback_route(config);
+        let payload = push_payload();
+        for (path, status) in [
+            ("/hooks/github", StatusCode::OK),
+            ("/github/callback", StatusCode::NOT_FOUND),
+            ("/hooks/github/callback", StatusCode::NOT_FOUND),
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .path(path)
+                .header("X-GitHub-Event", "push")
+                .header("X-Hub-Signature-256", sign("secret", &payload))
+                .body(&payload)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), status, "{}", path);
+        }
+        assert_eq!(sink.take().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_push_route_with_bad_signature() {
+        let (route, sink) = callback_route(signed_config());
+        let payload = push_payload();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "push")
+            .header("X-Hub-Signature-256", sign("wrong secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()["X-Request-Id"].len(), 8);
+        assert!(sink.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_signature_test_route() {
+        let mut config = signed_config();
+        config.debug = true;
+        config.require_known_repo = true;
+        add_test_project(
+            &mut config,
+            "smogon/pokemon-showdown",
+            r#"{"rooms": ["room"]}"#,
+        );
+        let route = test_route(Box::leak(Box::new(config)));
+        let payload = push_payload();
+        let unknown = payload.replace("smogon/pokemon-showdown", "smogon/other");
+        for (payload, signature, status, body) in [
+            (
+                &payload,
+                sign("secret", &payload),
+                StatusCode::OK,
+                serde_json::json!({ "signature": "valid" }),
+            ),
+            (
+                &payload,
+                sign("wrong", &payload),
+                StatusCode::UNAUTHORIZED,
+                serde_json::json!({
+                    "signature": "invalid",
+                    "error": "Signature doesn't match the payload",
+                }),
+            ),
+            (
+                &unknown,
+                sign("secret", &unknown),
+                StatusCode::NOT_FOUND,
+                serde_json::json!({ "error": "Repository smogon/other is not configured" }),
+            ),
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/github/test")
+                .header("X-Hub-Signature-256", signature)
+                .body(payload)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), status);
+            let reply: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(reply, body);
+        }
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/test")
+            .body("{")
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_signature_test_route_follows_webhook_path() {
+        let mut config = signed_config();
+        config.debug = true;
+        config.webhook_path = vec!["hooks".into(), "github".into()];
+        let route = test_route(Box::leak(Box::new(config)));
+        let payload = push_payload();
+        for (path, status) in [
+            ("/hooks/test", StatusCode::OK),
+            ("/github/test", StatusCode::NOT_FOUND),
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .path(path)
+                .header("X-Hub-Signature-256", sign("secret", &payload))
+                .body(&payload)
+                .reply(&route)
+                .await;
+            assert_eq!(response.status(), status, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signature_test_route_requires_debug() {
+        let route = test_route(Box::leak(Box::new(signed_config())));
+        let payload = push_payload();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/test")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_event() {
+        let (route, sink) = callback_route(signed_config());
+        let payload = push_payload();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "sponsorship")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(sink.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_payload_too_large() {
+        let mut config = test_config(Some("room"));
+        config.max_body_bytes = 64;
+        let (route, _) = callback_route(config);
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "push")
+            .body(vec![b' '; 65])
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let (route, _) = callback_route(test_config(Some("room")));
+        let payload = serde_json::json!({
+            "zen": "Keep it logically awesome.",
+            "hook_id": 123,
+            "repository": { "full_name": "smogon/pokemon-showdown" },
+        });
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "ping")
+            .body(payload.to_string())
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "pong");
+    }
+
+    async fn status(rejection: Rejection) -> StatusCode {
+        recover(rejection).await.unwrap().into_response().status()
+    }
+
+    /// Never finishes sending, reporting when a message is first sent.
+    struct StalledSink {
+        started: Mutex<Option<oneshot::Sender<()>>>,
+    }
+
+    impl MessageSink for StalledSink {
+        fn send<'a>(
+            &'a self,
+            _room: &'a str,
+            _message: SendMessage,
+            _priority: Priority,
+        ) -> BoxFuture<'a, Result<(), SendError>> {
+            if let Some(started) = self.started.lock().unwrap().take() {
+                started.send(()).ok();
+            }
+            Box::pin(future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_too_many_concurrent_webhooks() {
+        let mut config = signed_config();
+        config.max_concurrent_webhooks = 1;
+        let config = Box::leak(Box::new(config));
+        let connections = Arc::new(Connections::new(&config.servers));
+        let (started, started_rx) = oneshot::channel();
+        let sink = StalledSink {
+            started: Mutex::new(Some(started)),
+        };
+        connections.connect(0, Arc::new(sink));
+        let route = get_route(config, connections, Arc::default()).recover(recover);
+        let request = |event, payload: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/github/callback")
+                .header("X-GitHub-Event", event)
+                .header("X-Hub-Signature-256", sign("secret", payload))
+                .body(payload)
+        };
+        let payload = push_payload();
+        let in_flight = tokio::spawn({
+            let request = request("push", &payload);
+            let route = route.clone();
+            async move { request.reply(&route).await }
+        });
+        started_rx.await.unwrap();
+        let response = request("push", &payload).reply(&route).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        in_flight.abort();
+        assert!(in_flight.await.unwrap_err().is_cancelled());
+        let ping = serde_json::json!({
+            "zen": "Keep it logically awesome.",
+            "hook_id": 123,
+            "repository": { "full_name": "smogon/pokemon-showdown" },
+        });
+        let response = request("ping", &ping.to_string()).reply(&route).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_signature_error_is_unauthorized() {
+        let status = status(reject(SignatureError::Mismatch)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_json_error_is_bad_request() {
+        let error = serde_json::from_str::<()>("{").unwrap_err();
+        assert_eq!(status(reject(error)).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_send_error_is_service_unavailable() {
+        let status = status(reject(SendError::Disconnected)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_forbidden_address_is_forbidden() {
+        let status = status(reject(ForbiddenAddress(None))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_repository_is_not_found() {
+        let status = status(reject(UnknownRepository("a/b".into()))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_repository_in_strict_mode() {
+        let mut config = signed_config();
+        config.require_known_repo = true;
+        add_test_project(&mut config, "smogon/other", r#"{"rooms": ["room"]}"#);
+        let (route, sink) = callback_route(config);
+        let payload = push_payload();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/github/callback")
+            .header("X-GitHub-Event", "push")
+            .header("X-Hub-Signature-256", sign("secret", &payload))
+            .body(&payload)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.body(),
+            "Repository smogon/pokemon-showdown is not configured",
+        );
+        assert!(response.headers().contains_key("X-Request-Id"));
+        assert!(sink.take().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_other_rejections_are_passed_through() {
+        assert!(recover(warp::reject::not_found()).await.is_err());
+    }
 }
@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time;
+
+/// Recently announced pull requests, by repository full name and number, so
+/// that a burst of events for one pull request is only announced once.
+#[derive(Default)]
+pub struct RecentPullRequests {
+    recent: Mutex<HashSet<(String, u32)>>,
+}
+
+impl RecentPullRequests {
+    /// Returns whether a pull request wasn't announced within the last
+    /// `window`, and if so, marks it as announced.
+    pub fn insert(self: &Arc<Self>, full_name: &str, number: u32, window: Duration) -> bool {
+        if window.is_zero() {
+            return true;
+        }
+        let key = (full_name.to_string(), number);
+        if !self.recent.lock().unwrap().insert(key.clone()) {
+            return false;
+        }
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            time::sleep(window).await;
+            this.recent.lock().unwrap().remove(&key);
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RecentPullRequests;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::time;
+
+    const WINDOW: Duration = Duration::from_secs(600);
+
+    #[tokio::test]
+    async fn test_same_number_in_other_repository() {
+        let recent = Arc::new(RecentPullRequests::default());
+        assert!(recent.insert("smogon/pokemon-showdown", 5, WINDOW));
+        assert!(!recent.insert("smogon/pokemon-showdown", 5, WINDOW));
+        assert!(recent.insert("smogon/pokemon-showdown-client", 5, WINDOW));
+        assert!(recent.insert("smogon/pokemon-showdown", 6, WINDOW));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_window_expires() {
+        let recent = Arc::new(RecentPullRequests::default());
+        assert!(recent.insert("smogon/pokemon-showdown", 5, WINDOW));
+        time::sleep(WINDOW + Duration::from_secs(1)).await;
+        assert!(recent.insert("smogon/pokemon-showdown", 5, WINDOW));
+    }
+
+    #[tokio::test]
+    async fn test_zero_window() {
+        let recent = Arc::new(RecentPullRequests::default());
+        assert!(recent.insert("smogon/pokemon-showdown", 5, Duration::ZERO));
+        assert!(recent.insert("smogon/pokemon-showdown", 5, Duration::ZERO));
+    }
+}
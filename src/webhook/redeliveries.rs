@@ -0,0 +1,61 @@
+use lru::LruCache;
+use std::sync::Mutex;
+
+/// How many delivery IDs are remembered. GitHub only allows redelivering
+/// recent webhooks, so older ones don't need to be kept.
+const CAPACITY: usize = 1000;
+
+/// IDs of recently handled deliveries, from the `X-GitHub-Delivery` header, so
+/// that webhooks redelivered by GitHub aren't announced again.
+pub struct HandledDeliveries {
+    ids: Mutex<LruCache<String, ()>>,
+}
+
+impl Default for HandledDeliveries {
+    fn default() -> Self {
+        Self {
+            ids: Mutex::new(LruCache::new(CAPACITY)),
+        }
+    }
+}
+
+impl HandledDeliveries {
+    /// Remembers a delivery, returning `false` if it was already handled.
+    pub fn insert(&self, id: &str) -> bool {
+        let mut ids = self.ids.lock().unwrap();
+        if ids.contains(id) {
+            return false;
+        }
+        ids.put(id.into(), ());
+        true
+    }
+
+    /// Forgets a delivery that failed, so that its redelivery is handled.
+    pub fn remove(&self, id: &str) {
+        self.ids.lock().unwrap().pop(id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HandledDeliveries, CAPACITY};
+
+    #[test]
+    fn test_oldest_are_forgotten() {
+        let handled = HandledDeliveries::default();
+        for i in 0..=CAPACITY {
+            assert!(handled.insert(&i.to_string()));
+        }
+        assert!(handled.insert("0"));
+        assert!(!handled.insert(&CAPACITY.to_string()));
+    }
+
+    #[test]
+    fn test_removed_is_handled_again() {
+        let handled = HandledDeliveries::default();
+        assert!(handled.insert("id"));
+        assert!(!handled.insert("id"));
+        handled.remove("id");
+        assert!(handled.insert("id"));
+    }
+}
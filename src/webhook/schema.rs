@@ -1,11 +1,19 @@
-use crate::config::UsernameAliases;
-use crate::github_api::{GitHubApi, User};
+use super::{emoji, timestamp};
+use crate::config::{ActionVerbs, CommitTimestamps, UsernameAliases};
+use crate::discord::{self, Embed};
+use crate::github_api::{CommitStats, GitHubApi, User};
+use crate::glob;
+use crate::template_overrides::TemplateOverrides;
 use askama::Template;
 use htmlescape::encode_minimal as h;
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
-use serde::Deserialize;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Deserialize)]
 pub struct InitialPayload<'a> {
@@ -19,36 +27,133 @@ pub struct InitialRepository<'a> {
     pub full_name: Cow<'a, str>,
 }
 
+/// Sent by GitHub when a webhook is created.
+#[derive(Deserialize)]
+pub struct PingEvent<'a> {
+    #[serde(borrow)]
+    pub zen: Cow<'a, str>,
+    pub hook_id: u64,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PushEvent<'a> {
     #[serde(borrow, rename = "ref")]
     git_ref: Cow<'a, str>,
     #[serde(borrow)]
     commits: Vec<Commit<'a>>,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(borrow, default)]
+    pusher: Option<Pusher<'a>>,
+    /// Link to a comparison of all pushed changes.
+    #[serde(borrow, default)]
+    compare: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
     #[serde(borrow)]
     pub repository: Repository<'a>,
 }
 
+/// The person who pushed, who isn't necessarily the author of the commits.
+#[derive(Debug, Deserialize)]
+struct Pusher<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(borrow, default)]
+    email: Option<Cow<'a, str>>,
+}
+
+#[derive(Clone, Copy)]
+pub struct ViewContext<'a> {
+    pub username_aliases: &'a UsernameAliases,
+    pub repository_aliases: &'a HashMap<String, String>,
+    pub action_verbs: &'a ActionVerbs,
+    /// Show `pokemon-showdown` and `pokemon-showdown-client` as `server` and
+    /// `client`.
+    pub showdown_repository_names: bool,
+    /// Replace emoji shortcodes in commit messages.
+    pub emoji_shortcodes: bool,
+    pub max_message_length: usize,
+    pub pull_request_preview_length: usize,
+    pub show_avatars: bool,
+    pub repository_color: &'a str,
+}
+
 pub struct PushEventContext<'a> {
     pub github_api: Option<&'a mut GitHubApi>,
-    pub username_aliases: &'a UsernameAliases,
+    pub view: ViewContext<'a>,
+    pub hide_merge_commits: bool,
+    /// Look up GitHub logins of commit authors without a linked account.
+    pub resolve_commit_authors: bool,
+    /// Fetch GitHub profiles of commit authors, otherwise authors are shown
+    /// without any GitHub API requests.
+    pub enrich_authors: bool,
+    /// Show consecutive commits by the same author under one author header.
+    pub group_commits: bool,
+    /// Fetch how many lines each commit added and removed.
+    pub commit_stats: bool,
+    /// Show the branch for pushes to the default branch too, pushes to other
+    /// branches always show it.
+    pub show_default_branch: bool,
+    pub commit_timestamps: CommitTimestamps,
+    /// Show Gravatar avatars of commit authors without a GitHub login.
+    pub gravatars: bool,
+    /// Fetch the repository description and star count, shown as a tooltip
+    /// of the repository name.
+    pub repository_summary: bool,
 }
 
 macro_rules! view_method {
     ($name:ident($s:ident, $($ex:tt)*)) => {
+        /// Renders the push, showing only authors fetched earlier by
+        /// [`PushEvent::prefetch_authors`].
         pub async fn $name<'a>(&'a $s, mut ctx: PushEventContext<'a>) -> ViewPushEvent<'a> {
-            let mut commits_view = Vec::new();
-            for commit in &$s.commits {
-                commits_view.push(
-                    commit
-                        .$name($($ex)* &mut ctx)
+            let group_commits = ctx.group_commits;
+            let mut groups: Vec<CommitGroup> = Vec::new();
+            let mut previous_author = None;
+            for commit in $s.visible_commits(ctx.hide_merge_commits) {
+                let mut view = commit
+                    .$name(&$s.repository.full_name, $($ex)* &mut ctx)
+                    .await;
+                if !group_commits {
+                    groups.push(CommitGroup {
+                        author: None,
+                        commits: vec![view.to_string()],
+                    });
+                    continue;
+                }
+                let author = view.author.take();
+                match groups.last_mut() {
+                    Some(group) if previous_author == Some(&commit.author) => {
+                        group.commits.push(view.to_string());
+                    }
+                    _ => groups.push(CommitGroup {
+                        author: author.map(|author| author.to_string()),
+                        commits: vec![view.to_string()],
+                    }),
+                }
+                previous_author = Some(&commit.author);
+            }
+            let pusher = $s.to_pusher_view(ctx.view.username_aliases, ctx.hide_merge_commits);
+            let mut repository = $s.repository.to_view(ctx.view);
+            match ctx.github_api {
+                Some(github_api) if ctx.repository_summary => {
+                    repository.summary = github_api
+                        .fetch_repository(&$s.repository.full_name)
                         .await
-                        .to_string(),
-                );
+                        .map(|metadata| metadata.summary());
+                }
+                _ => {}
             }
+            let branch = $s.branch().filter(|&branch| {
+                ctx.show_default_branch || branch != $s.repository.default_branch
+            });
             ViewPushEvent {
-                commits: commits_view,
-                repository: $s.repository.to_view(),
+                pusher,
+                branch,
+                groups,
+                repository,
+                compare_url: $s.compare.as_deref(),
             }
         }
     };
@@ -58,16 +163,242 @@ impl PushEvent<'_> {
     view_method!(to_view(self, &self.repository.html_url,));
     view_method!(to_simple_view(self,));
 
-    pub fn branch(&self) -> &str {
-        self.git_ref.rsplit('/').next().unwrap()
+    /// Fetches GitHub profiles of commit authors, once for all views of the
+    /// push.
+    pub async fn prefetch_authors(&self, ctx: &mut PushEventContext<'_>) {
+        let github_api = match &mut ctx.github_api {
+            Some(github_api) if ctx.enrich_authors => github_api,
+            _ => return,
+        };
+        let mut user_names = Vec::new();
+        for commit in self.visible_commits(ctx.hide_merge_commits) {
+            match &commit.author.username {
+                Some(username) => user_names.push(username.to_string()),
+                None if ctx.resolve_commit_authors => {
+                    let login = github_api
+                        .fetch_commit_author(&self.repository.full_name, &commit.id)
+                        .await;
+                    user_names.extend(login);
+                }
+                None => {}
+            }
+        }
+        github_api.prefetch_users(user_names).await;
+    }
+
+    fn visible_commits(&self, hide_merge_commits: bool) -> impl Iterator<Item = &Commit<'_>> {
+        self.commits
+            .iter()
+            .filter(move |commit| !(hide_merge_commits && commit.is_merge()))
+    }
+
+    pub fn to_discord(&self, ctx: ViewContext<'_>, hide_merge_commits: bool) -> Embed {
+        let repository = self.repository.to_view(ctx);
+        let mut description = repository.to_markdown();
+        for commit in self.visible_commits(hide_merge_commits) {
+            description += &format!(
+                "\n{} {} ({})",
+                discord::link(&format!("`{}`", commit.short_id()), &commit.url),
+                discord::escape(commit.short_message(ctx.max_message_length)),
+                discord::escape(commit.author.display_name(ctx.username_aliases)),
+            );
+        }
+        Embed::new(description, repository.color)
+    }
+
+    /// Plain text messages, one for each commit.
+    pub fn to_text(&self, ctx: ViewContext<'_>, hide_merge_commits: bool) -> Vec<String> {
+        let repository = self.repository.to_view(ctx);
+        self.visible_commits(hide_merge_commits)
+            .map(|commit| {
+                format!(
+                    "[{}] {} {} ({}) {}",
+                    repository.name,
+                    commit.short_id(),
+                    commit.short_message(ctx.max_message_length),
+                    commit.author.display_name(ctx.username_aliases),
+                    commit.url,
+                )
+            })
+            .collect()
+    }
+
+    /// The pusher, if they didn't author all of the visible commits, such as
+    /// when rebasing someone else's commits.
+    fn to_pusher_view<'a>(
+        &'a self,
+        username_aliases: &'a UsernameAliases,
+        hide_merge_commits: bool,
+    ) -> Option<ViewPusher<'a>> {
+        let pusher = self.pusher.as_ref()?;
+        if self
+            .visible_commits(hide_merge_commits)
+            .all(|commit| commit.author.username.as_deref() == Some(&*pusher.name))
+        {
+            return None;
+        }
+        Some(ViewPusher {
+            name: username_aliases.get(&pusher.name),
+            email: pusher.email.as_deref(),
+            html_url: if self.sender.login == pusher.name {
+                self.sender
+                    .to_view(username_aliases, &self.repository)
+                    .html_url
+            } else {
+                Cow::Owned(self.repository.profile_url(&pusher.name))
+            },
+            commits: self.visible_commits(hide_merge_commits).count(),
+            files: self.changed_files(hide_merge_commits),
+        })
+    }
+
+    /// Counts distinct files changed by visible commits.
+    fn changed_files(&self, hide_merge_commits: bool) -> usize {
+        self.visible_commits(hide_merge_commits)
+            .flat_map(Commit::paths)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Drops commits that don't touch any file under `prefixes`.
+    pub fn retain_paths(&mut self, prefixes: &[String]) {
+        self.commits.retain(|commit| commit.touches_paths(prefixes));
+    }
+
+    pub fn has_visible_commits(&self, hide_merge_commits: bool) -> bool {
+        self.visible_commits(hide_merge_commits).next().is_some()
+    }
+
+    /// Name of the pushed branch, `None` for tag pushes.
+    pub fn branch(&self) -> Option<&str> {
+        self.git_ref.strip_prefix("refs/heads/")
+    }
+
+    fn tag(&self) -> Option<&str> {
+        self.git_ref.strip_prefix("refs/tags/")
+    }
+
+    pub fn is_tag(&self) -> bool {
+        self.tag().is_some()
+    }
+
+    /// Returns whether this push created or moved a tag that looks like a
+    /// version number, such as `v1.2.3` or `2.0.0-beta.1`.
+    pub fn is_release_tag(&self) -> bool {
+        static SEMVER_PATTERN: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^v?[0-9]+\.[0-9]+\.[0-9]+(-[0-9A-Za-z.-]+)?(\+[0-9A-Za-z.-]+)?$").unwrap()
+        });
+        match self.tag() {
+            Some(tag) => !self.deleted && SEMVER_PATTERN.is_match(tag),
+            None => false,
+        }
+    }
+
+    pub fn to_tag_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewTagPushEvent<'a> {
+        ViewTagPushEvent {
+            tag: self.tag().unwrap_or(&self.git_ref),
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "tag_push_event.html")]
+pub struct ViewTagPushEvent<'a> {
+    tag: &'a str,
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewTagPushEvent<'_> {
+    fn release_url(&self) -> String {
+        format!(
+            "{}/releases/tag/{}",
+            self.repository.html_url.trim_end_matches('/'),
+            self.tag,
+        )
+    }
+
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} tagged **🏷 {}**",
+            self.sender.to_markdown(),
+            discord::link(&discord::escape(self.tag), &self.release_url()),
+        ))
     }
 }
 
 #[derive(Template)]
 #[template(path = "push_event.html")]
 pub struct ViewPushEvent<'a> {
-    commits: Vec<String>,
+    pusher: Option<ViewPusher<'a>>,
+    branch: Option<&'a str>,
+    groups: Vec<CommitGroup>,
     repository: ViewRepository<'a>,
+    compare_url: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "pusher.html")]
+struct ViewPusher<'a> {
+    name: &'a str,
+    email: Option<&'a str>,
+    html_url: Cow<'a, str>,
+    commits: usize,
+    /// Distinct files changed, 0 when the payload doesn't list them.
+    files: usize,
+}
+
+/// Consecutive commits, with their author shown once when commits are
+/// grouped, otherwise each commit shows its own author.
+struct CommitGroup {
+    author: Option<String>,
+    commits: Vec<String>,
+}
+
+impl ViewPushEvent<'_> {
+    /// Renders the push, using the `push_event.html` override for each commit
+    /// if there is one. Overrides don't show author headers of grouped commits.
+    pub fn render(&self, overrides: &TemplateOverrides) -> String {
+        let repository = self.repository.to_string();
+        let commits: Option<Vec<_>> = self
+            .groups
+            .iter()
+            .flat_map(|group| &group.commits)
+            .map(|commit| {
+                let values = [("repository", &*repository), ("commit", commit)];
+                overrides.render("push_event.html", &values)
+            })
+            .collect();
+        match commits {
+            Some(commits) => commits.join("<br>"),
+            None => self.to_string(),
+        }
+    }
+
+    /// Renders the push like [`Self::render`], dropping trailing commits that
+    /// don't fit in `max_length` bytes and linking to all of them instead.
+    pub fn render_within(mut self, overrides: &TemplateOverrides, max_length: usize) -> String {
+        let mut html = self.render(overrides);
+        let more = match self.compare_url {
+            Some(url) => format!("<br><a href='{}'>&hellip;and more</a>", h(url)),
+            None => "<br>&hellip;and more".into(),
+        };
+        while html.len() > max_length && self.commit_count() > 1 {
+            let group = self.groups.last_mut().unwrap();
+            group.commits.pop();
+            if group.commits.is_empty() {
+                self.groups.pop();
+            }
+            html = self.render(overrides) + &more;
+        }
+        html
+    }
+
+    fn commit_count(&self) -> usize {
+        self.groups.iter().map(|group| group.commits.len()).sum()
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -80,35 +411,176 @@ struct Commit<'a> {
     author: Author<'a>,
     #[serde(borrow)]
     url: Cow<'a, str>,
+    #[serde(default)]
+    verification: Option<Verification>,
+    #[serde(borrow, default)]
+    added: Vec<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    modified: Vec<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    removed: Vec<Cow<'a, str>>,
+    /// When the commit was authored, in RFC 3339 format.
+    #[serde(borrow, default)]
+    timestamp: Option<Cow<'a, str>>,
+}
+
+/// Signature verification status, not present in all payloads.
+#[derive(Debug, Deserialize)]
+struct Verification {
+    verified: bool,
 }
 
 impl Commit<'_> {
-    async fn to_view<'a>(&'a self, url: &str, ctx: &'a mut PushEventContext<'_>) -> ViewCommit<'a> {
-        let message = self.short_message();
+    fn short_id(&self) -> &str {
+        short_id(&self.id)
+    }
+
+    /// Files the commit added, modified, or removed.
+    fn paths(&self) -> impl Iterator<Item = &str> {
+        self.added
+            .iter()
+            .chain(&self.modified)
+            .chain(&self.removed)
+            .map(|path| &**path)
+    }
+
+    /// Returns whether this commit added, modified or removed a file whose
+    /// path starts with any of `prefixes`.
+    fn touches_paths(&self, prefixes: &[String]) -> bool {
+        self.paths()
+            .any(|path| prefixes.iter().any(|prefix| path.starts_with(&**prefix)))
+    }
+
+    async fn to_view<'a>(
+        &'a self,
+        full_name: &str,
+        url: &str,
+        ctx: &'a mut PushEventContext<'_>,
+    ) -> ViewCommit<'a> {
+        let message = self.short_message(ctx.view.max_message_length);
+        let stats = match &mut ctx.github_api {
+            Some(github_api) if ctx.commit_stats => {
+                github_api.fetch_commit_stats(full_name, &self.id).await
+            }
+            _ => None,
+        };
+        let time = self.format_timestamp(ctx.commit_timestamps);
         ViewCommit {
-            id: &self.id[..6],
+            id: self.short_id(),
             message,
             full_message: &self.message,
-            formatted_message: format_title(message, url),
-            author: self.author.to_view(ctx).await,
+            formatted_message: format_title(message, url, ctx.view.emoji_shortcodes),
+            author: Some(self.author.to_view(full_name, &self.id, ctx).await),
             url: &self.url,
+            verified: self.is_verified(),
+            revert: self.is_revert(),
+            stats,
+            time,
+        }
+    }
+
+    fn format_timestamp(&self, mode: CommitTimestamps) -> Option<String> {
+        let time = timestamp::parse_rfc3339(self.timestamp.as_deref()?)?;
+        match mode {
+            CommitTimestamps::Hidden => None,
+            CommitTimestamps::Relative => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs() as i64);
+                Some(timestamp::format_relative(time, now))
+            }
+            CommitTimestamps::Absolute => Some(timestamp::format_utc(time)),
         }
     }
 
+    fn is_revert(&self) -> bool {
+        self.message.starts_with("Revert \"")
+    }
+
+    fn is_verified(&self) -> bool {
+        matches!(self.verification, Some(Verification { verified: true }))
+    }
+
     async fn to_simple_view<'a>(
         &'a self,
+        full_name: &str,
         ctx: &'a mut PushEventContext<'_>,
     ) -> ViewSimpleCommit<'a> {
         ViewSimpleCommit {
-            message: self.short_message(),
+            message: self.short_message(ctx.view.max_message_length),
             full_message: &self.message,
-            author: self.author.to_view(ctx).await,
+            author: Some(self.author.to_view(full_name, &self.id, ctx).await),
             url: &self.url,
         }
     }
 
-    fn short_message(&self) -> &str {
-        self.message.split('\n').next().unwrap()
+    fn is_merge(&self) -> bool {
+        // Push payloads don't include commit parents, so rely on the message
+        // GitHub and git generate for merges.
+        self.message.starts_with("Merge ")
+    }
+
+    fn short_message(&self, max_length: usize) -> &str {
+        truncate(first_line(&self.message), max_length)
+    }
+}
+
+/// First six characters of a commit id, or the whole id if it's shorter.
+fn short_id(id: &str) -> &str {
+    match id.char_indices().nth(6) {
+        Some((index, _)) => &id[..index],
+        None => id,
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.split('\n').next().unwrap()
+}
+
+fn truncate(text: &str, max_length: usize) -> &str {
+    match text.char_indices().nth(max_length) {
+        Some((index, _)) => text[..index].trim_end(),
+        None => text,
+    }
+}
+
+/// Turns the start of a Markdown description into a short plain text preview.
+///
+/// Only the first two non-empty lines are used. HTML comments (common in pull
+/// request templates), headings, quotes, list markers, emphasis and link
+/// targets are removed.
+fn preview(markdown: &str, max_length: usize) -> Option<String> {
+    static COMMENT_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<!--.*?-->").unwrap());
+    static LINK_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap());
+    if max_length == 0 {
+        return None;
+    }
+    let markdown = COMMENT_PATTERN.replace_all(markdown, "");
+    let text = markdown
+        .lines()
+        .map(|line| {
+            let line = line.trim().trim_start_matches(&['#', '>'][..]).trim_start();
+            let line = line
+                .strip_prefix("- ")
+                .or_else(|| line.strip_prefix("* "))
+                .unwrap_or(line);
+            LINK_PATTERN
+                .replace_all(line, "$1")
+                .replace("**", "")
+                .replace("__", "")
+                .replace('`', "")
+        })
+        .filter(|line| !line.is_empty())
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let truncated = truncate(&text, max_length);
+    if truncated.is_empty() {
+        None
+    } else if truncated.len() < text.len() {
+        Some(format!("{}…", truncated))
+    } else {
+        Some(text)
     }
 }
 
@@ -119,8 +591,13 @@ struct ViewCommit<'a> {
     message: &'a str,
     full_message: &'a str,
     formatted_message: String,
-    author: ViewAuthor<'a>,
+    author: Option<ViewAuthor<'a>>,
     url: &'a str,
+    verified: bool,
+    revert: bool,
+    stats: Option<CommitStats>,
+    /// Authoring time, shown when hovering over the commit ID.
+    time: Option<String>,
 }
 
 #[derive(Template)]
@@ -128,44 +605,93 @@ struct ViewCommit<'a> {
 struct ViewSimpleCommit<'a> {
     message: &'a str,
     full_message: &'a str,
-    author: ViewAuthor<'a>,
+    author: Option<ViewAuthor<'a>>,
     url: &'a str,
 }
 
-fn format_title(message: &str, url: &str) -> String {
+fn format_title(message: &str, url: &str, emoji_shortcodes: bool) -> String {
     static ISSUE_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r#"#([0-9]+)"#).unwrap());
+    let mut message = h(message);
+    if emoji_shortcodes {
+        message = emoji::replace_shortcodes(&message).into_owned();
+    }
     ISSUE_PATTERN
-        .replace_all(&h(message), |c: &Captures| {
+        .replace_all(&message, |c: &Captures| {
             format!("<a href='{}/issues/{}'>{}</a>", h(url), h(&c[1]), &c[0])
         })
         .to_string()
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, PartialEq)]
 struct Author<'a> {
     #[serde(borrow)]
     name: Cow<'a, str>,
+    #[serde(borrow)]
+    email: Option<Cow<'a, str>>,
     username: Option<String>,
 }
 
 impl Author<'_> {
-    async fn to_view<'a>(&'a self, ctx: &'a mut PushEventContext<'_>) -> ViewAuthor<'a> {
-        let username = if let Some(username) = &self.username {
-            let github_metadata = if let Some(github_api) = &mut ctx.github_api {
-                github_api.fetch_user(username).await
-            } else {
-                None
+    async fn to_view<'a>(
+        &'a self,
+        full_name: &str,
+        commit_id: &str,
+        ctx: &'a mut PushEventContext<'_>,
+    ) -> ViewAuthor<'a> {
+        let login = match &self.username {
+            Some(username) => Some(Cow::Borrowed(username)),
+            None => Self::fetch_login(full_name, commit_id, ctx)
+                .await
+                .map(Cow::Owned),
+        };
+        let username = if let Some(login) = login {
+            let github_metadata = match &mut ctx.github_api {
+                Some(github_api) if ctx.enrich_authors => github_api.cached_user(&login),
+                _ => None,
             };
+            let aliases = ctx.view.username_aliases;
             Some(Username {
-                username: ctx.username_aliases.get(username),
+                username: match login {
+                    Cow::Borrowed(login) => Cow::Borrowed(aliases.get(login)),
+                    Cow::Owned(login) => Cow::Owned(aliases.get(&login).into()),
+                },
                 github_metadata,
+                show_avatar: ctx.view.show_avatars,
             })
         } else {
             None
         };
+        let gravatar = match &self.email {
+            Some(email) if username.is_none() && ctx.gravatars && ctx.view.show_avatars => {
+                Some(gravatar_url(email))
+            }
+            _ => None,
+        };
         ViewAuthor {
             name: &self.name,
             username,
+            gravatar,
+        }
+    }
+
+    /// The aliased GitHub login, or the commit author name without one.
+    fn display_name<'a>(&'a self, username_aliases: &'a UsernameAliases) -> &'a str {
+        match &self.username {
+            Some(username) => username_aliases.get(username),
+            None => &self.name,
+        }
+    }
+
+    async fn fetch_login(
+        full_name: &str,
+        commit_id: &str,
+        ctx: &mut PushEventContext<'_>,
+    ) -> Option<String> {
+        match &mut ctx.github_api {
+            Some(github_api) if ctx.resolve_commit_authors && ctx.enrich_authors => {
+                github_api.fetch_commit_author(full_name, commit_id).await
+            }
+            _ => None,
         }
     }
 }
@@ -175,13 +701,28 @@ impl Author<'_> {
 struct ViewAuthor<'a> {
     name: &'a str,
     username: Option<Username<'a>>,
+    gravatar: Option<String>,
+}
+
+/// Gravatar image of an email address, with an identicon for addresses
+/// without one.
+///
+/// Gravatar accepts SHA-256 email hashes as well as MD5 ones, SHA-256 is used
+/// as the `sha2` crate is already a dependency for webhook signatures.
+fn gravatar_url(email: &str) -> String {
+    let hash = Sha256::digest(email.trim().to_lowercase().as_bytes());
+    format!(
+        "https://www.gravatar.com/avatar/{}?s=32&d=identicon",
+        hex::encode(hash),
+    )
 }
 
 #[derive(Template)]
 #[template(path = "username.html")]
 struct Username<'a> {
-    username: &'a str,
+    username: Cow<'a, str>,
     github_metadata: Option<&'a User>,
+    show_avatar: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -189,21 +730,44 @@ pub struct Repository<'a> {
     #[serde(borrow)]
     name: Cow<'a, str>,
     #[serde(borrow)]
+    pub full_name: Cow<'a, str>,
+    #[serde(borrow)]
     html_url: Cow<'a, str>,
     #[serde(borrow)]
     pub default_branch: Cow<'a, str>,
+    #[serde(default)]
+    stargazers_count: u32,
 }
 
 impl Repository<'_> {
-    fn to_view(&self) -> ViewRepository<'_> {
-        let name = match &*self.name {
-            "pokemon-showdown" => "server",
-            "pokemon-showdown-client" => "client",
-            name => name,
+    /// Profile URL of `login` on the host the repository is on, which isn't
+    /// github.com for GitHub Enterprise.
+    fn profile_url(&self, login: &str) -> String {
+        let host_start = self.html_url.find("://").map_or(0, |i| i + 3);
+        let host_end = self.html_url[host_start..]
+            .find('/')
+            .map_or(self.html_url.len(), |i| host_start + i);
+        format!("{}/{}", &self.html_url[..host_end], login)
+    }
+
+    fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewRepository<'a> {
+        let aliases = ctx.repository_aliases;
+        let name = match aliases
+            .get(&*self.full_name)
+            .or_else(|| aliases.get(&*self.name))
+        {
+            Some(alias) => alias,
+            None => match &*self.name {
+                "pokemon-showdown" if ctx.showdown_repository_names => "server",
+                "pokemon-showdown-client" if ctx.showdown_repository_names => "client",
+                name => name,
+            },
         };
         ViewRepository {
             name,
             html_url: &self.html_url,
+            color: ctx.repository_color,
+            summary: None,
         }
     }
 }
@@ -213,6 +777,23 @@ impl Repository<'_> {
 pub struct ViewRepository<'a> {
     name: &'a str,
     html_url: &'a str,
+    color: &'a str,
+    summary: Option<String>,
+}
+
+impl ViewRepository<'_> {
+    fn to_markdown(&self) -> String {
+        let name = discord::escape(&format!("[{}]", self.name));
+        format!("**{}**", discord::link(&name, self.html_url))
+    }
+
+    /// An embed with the repository name followed by `description`.
+    fn embed(&self, description: String) -> Embed {
+        Embed::new(
+            format!("{} {}", self.to_markdown(), description),
+            self.color,
+        )
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -225,22 +806,58 @@ pub struct PullRequestEvent<'a> {
     pub repository: Repository<'a>,
     #[serde(borrow)]
     sender: Sender<'a>,
+    #[serde(borrow, default)]
+    changes: Option<Changes<'a>>,
 }
 
 impl PullRequestEvent<'_> {
-    pub fn to_view<'a>(
-        &'a self,
-        username_aliases: &'a UsernameAliases,
-    ) -> ViewPullRequestEvent<'a> {
+    /// Returns whether this is an edit that didn't change the title, such as
+    /// an edited description.
+    pub fn is_untitled_edit(&self) -> bool {
+        self.action == "edited" && self.renamed_from().is_none()
+    }
+
+    /// Returns the branch a pull request was merged into by this event.
+    pub fn merged_into(&self) -> Option<&str> {
+        if self.action == "closed" && self.pull_request.merged {
+            Some(&self.pull_request.base.as_ref()?.git_ref)
+        } else {
+            None
+        }
+    }
+
+    fn renamed_from(&self) -> Option<&str> {
+        let changes = self.changes.as_ref()?;
+        Some(&changes.title.as_ref()?.from)
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewPullRequestEvent<'a> {
+        let (action, action_color) = match &*self.action {
+            "synchronize" => ("updated", None),
+            "review_requested" => ("requested a review for", None),
+            "ready_for_review" => ("marked ready for review", None),
+            "closed" if self.pull_request.merged => ("merged", Some("6F42C1")),
+            action => (action, None),
+        };
+        // Merges are closes, but have their own wording.
+        let key = if self.action == "closed" && self.pull_request.merged {
+            "merged"
+        } else {
+            &self.action
+        };
+        let action = ctx.action_verbs.get("pull_request", key).unwrap_or(action);
+        let body = match &self.pull_request.body {
+            Some(body) if self.action == "opened" => preview(body, ctx.pull_request_preview_length),
+            _ => None,
+        };
         ViewPullRequestEvent {
-            action: match &*self.action {
-                "synchronize" => "updated",
-                "review_requested" => "requested a review for",
-                action => action,
-            },
+            action,
+            action_color,
+            renamed_from: self.renamed_from(),
             pull_request: &self.pull_request,
-            repository: self.repository.to_view(),
-            sender: self.sender.to_view(username_aliases),
+            body,
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
         }
     }
 }
@@ -249,11 +866,42 @@ impl PullRequestEvent<'_> {
 #[template(path = "pull_request_event.html")]
 pub struct ViewPullRequestEvent<'a> {
     action: &'a str,
+    action_color: Option<&'static str>,
+    renamed_from: Option<&'a str>,
     pull_request: &'a PullRequest<'a>,
+    body: Option<String>,
     repository: ViewRepository<'a>,
     sender: ViewSender<'a>,
 }
 
+impl ViewPullRequestEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        let pull_request = &self.pull_request;
+        let mut description = match self.renamed_from {
+            Some(renamed_from) => format!(
+                "{} renamed {} from '{}' to '{}'",
+                self.sender.to_markdown(),
+                discord::link(
+                    &format!("PR#{}", pull_request.number),
+                    &pull_request.html_url
+                ),
+                discord::escape(renamed_from),
+                discord::escape(&pull_request.title),
+            ),
+            None => format!(
+                "{} {} {}",
+                self.sender.to_markdown(),
+                self.action,
+                pull_request.to_markdown(),
+            ),
+        };
+        if let Some(body) = &self.body {
+            description += &format!("\n{}", discord::escape(body));
+        }
+        self.repository.embed(description)
+    }
+}
+
 #[derive(Debug, Deserialize, Template)]
 #[template(path = "pull_request.html")]
 pub struct PullRequest<'a> {
@@ -262,92 +910,1878 @@ pub struct PullRequest<'a> {
     html_url: Cow<'a, str>,
     #[serde(borrow)]
     title: Cow<'a, str>,
+    #[serde(borrow, default)]
+    labels: Vec<Label<'a>>,
+    #[serde(default)]
+    merged: bool,
+    #[serde(borrow, default)]
+    body: Option<Cow<'a, str>>,
+    #[serde(borrow, default)]
+    base: Option<Base<'a>>,
+}
+
+impl PullRequest<'_> {
+    fn to_markdown(&self) -> String {
+        format!(
+            "{}: {}",
+            discord::link(&format!("PR#{}", self.number), &self.html_url),
+            discord::escape(&self.title),
+        )
+    }
 }
 
 #[derive(Debug, Deserialize)]
-struct Sender<'a> {
+struct Base<'a> {
+    #[serde(borrow, rename = "ref")]
+    git_ref: Cow<'a, str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Changes<'a> {
     #[serde(borrow)]
-    login: Cow<'a, str>,
+    title: Option<Change<'a>>,
 }
 
-impl Sender<'_> {
-    fn to_view<'a>(&'a self, username_aliases: &'a UsernameAliases) -> ViewSender<'a> {
-        ViewSender {
-            login: &self.login,
-            renamed_login: username_aliases.get(&self.login),
-        }
-    }
+#[derive(Debug, Deserialize)]
+struct Change<'a> {
+    #[serde(borrow)]
+    from: Cow<'a, str>,
 }
 
-struct ViewSender<'a> {
-    login: &'a str,
-    renamed_login: &'a str,
+#[derive(Debug, Deserialize)]
+struct Label<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    /// Hex colour, `None` unless it's exactly six hex digits as it's
+    /// inserted into a `style` attribute.
+    #[serde(deserialize_with = "deserialize_label_color")]
+    color: Option<Cow<'a, str>>,
 }
 
-#[cfg(test)]
-mod test {
-    use super::{
-        Author, Commit, PullRequest, PullRequestEvent, PushEvent, PushEventContext, Repository,
-        Sender,
-    };
-    use crate::config::UsernameAliases;
+fn deserialize_label_color<'de: 'a, 'a, D>(
+    deserializer: D,
+) -> Result<Option<Cow<'a, str>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let color = Cow::<str>::deserialize(deserializer)?;
+    let is_valid = color.len() == 6 && color.bytes().all(|b| b.is_ascii_hexdigit());
+    Ok(if is_valid { Some(color) } else { None })
+}
 
-    fn sample_commit() -> Commit<'static> {
-        Commit {
-            id: "0da2590a700d054fc2ce39ddc9c95f360329d9be".into(),
-            message: "Hello, world!".into(),
-            author: Author {
-                name: "Konrad Borowski".into(),
-                username: Some("xfix".into()),
+#[derive(Debug, Deserialize)]
+pub struct PullRequestReviewEvent<'a> {
+    #[serde(borrow)]
+    pub action: Cow<'a, str>,
+    #[serde(borrow)]
+    pub review: Review<'a>,
+    #[serde(borrow)]
+    pull_request: PullRequest<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl PullRequestReviewEvent<'_> {
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewPullRequestReviewEvent<'a> {
+        ViewPullRequestReviewEvent {
+            state: match &*self.review.state {
+                "approved" => "approved",
+                "changes_requested" => "requested changes to",
+                "commented" => "commented on",
+                state => state,
             },
-            url: "http://example.com".into(),
+            pull_request: &self.pull_request,
+            repository: self.repository.to_view(ctx),
+            reviewer: self
+                .review
+                .user
+                .to_view(ctx.username_aliases, &self.repository),
         }
     }
+}
 
-    #[tokio::test]
-    async fn test_push_event() {
-        let commit = concat!(
-            "[<a href='https://github.com/smogon/pokemon-showdown'>",
-            "<font color=FF00FF>server</font></a>] ",
-            "<a href='http://example.com'><font color=606060><kbd>0da259</kbd></font></a>\n",
-            "<span title='Hello, world!'>Hello, world!</span> ",
-            r#"<font color=909090 title="Konrad Borowski">(xfix)</font>"#,
-        );
-        assert_eq!(
-            PushEvent {
-                git_ref: "refs/head/master".into(),
-                commits: vec![sample_commit(), sample_commit()],
-                repository: Repository {
-                    name: "pokemon-showdown".into(),
-                    html_url: "https://github.com/smogon/pokemon-showdown".into(),
-                    default_branch: "master".into(),
-                }
-            }
-            .to_view(PushEventContext {
-                github_api: None,
-                username_aliases: &UsernameAliases::default(),
-            })
-            .await
-            .to_string(),
-            format!("{0}<br>{0}", commit)
-        );
+#[derive(Debug, Deserialize)]
+pub struct Review<'a> {
+    #[serde(borrow)]
+    pub state: Cow<'a, str>,
+    #[serde(borrow)]
+    user: Sender<'a>,
+    #[serde(borrow)]
+    pub body: Option<Cow<'a, str>>,
+}
+
+#[derive(Template)]
+#[template(path = "pull_request_review_event.html")]
+pub struct ViewPullRequestReviewEvent<'a> {
+    state: &'a str,
+    pull_request: &'a PullRequest<'a>,
+    repository: ViewRepository<'a>,
+    reviewer: ViewSender<'a>,
+}
+
+impl ViewPullRequestReviewEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} {} {}",
+            self.reviewer.to_markdown(),
+            self.state,
+            self.pull_request.to_markdown(),
+        ))
     }
+}
 
-    #[tokio::test]
-    async fn test_commit() {
-        assert_eq!(
-            sample_commit()
-                .to_view(
-                    "shouldn't be used",
-                    &mut PushEventContext {
-                        github_api: None,
-                        username_aliases: &UsernameAliases::default(),
-                    }
-                )
-                .await
-                .to_string(),
-            concat!(
-                "<a href='http://example.com'>",
+#[derive(Debug, Deserialize)]
+pub struct CommitCommentEvent<'a> {
+    #[serde(borrow)]
+    pub action: Cow<'a, str>,
+    #[serde(borrow)]
+    comment: CommitComment<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl CommitCommentEvent<'_> {
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewCommitCommentEvent<'a> {
+        ViewCommitCommentEvent {
+            commit_id: truncate(&self.comment.commit_id, 6),
+            html_url: &self.comment.html_url,
+            body: ViewSnippet::new(&self.comment.body, ctx.max_message_length),
+            repository: self.repository.to_view(ctx),
+            commenter: self
+                .comment
+                .user
+                .to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitComment<'a> {
+    #[serde(borrow)]
+    user: Sender<'a>,
+    #[serde(borrow)]
+    commit_id: Cow<'a, str>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+    #[serde(borrow)]
+    body: Cow<'a, str>,
+}
+
+#[derive(Template)]
+#[template(path = "commit_comment_event.html")]
+pub struct ViewCommitCommentEvent<'a> {
+    commit_id: &'a str,
+    html_url: &'a str,
+    body: ViewSnippet<'a>,
+    repository: ViewRepository<'a>,
+    commenter: ViewSender<'a>,
+}
+
+impl ViewCommitCommentEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} commented on {}: {}",
+            self.commenter.to_markdown(),
+            discord::link(&format!("commit `{}`", self.commit_id), self.html_url),
+            discord::escape(self.body.text),
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssueCommentEvent<'a> {
+    #[serde(borrow)]
+    pub action: Cow<'a, str>,
+    #[serde(borrow)]
+    issue: Issue<'a>,
+    #[serde(borrow)]
+    comment: Comment<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl IssueCommentEvent<'_> {
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewIssueCommentEvent<'a> {
+        ViewIssueCommentEvent {
+            is_pull_request: self.issue.pull_request.is_some(),
+            number: self.issue.number,
+            issue_url: &self.issue.html_url,
+            html_url: &self.comment.html_url,
+            body: ViewSnippet::new(&self.comment.body, ctx.max_message_length),
+            repository: self.repository.to_view(ctx),
+            commenter: self
+                .comment
+                .user
+                .to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Issue<'a> {
+    number: u32,
+    #[serde(borrow)]
+    title: Cow<'a, str>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+    pull_request: Option<IgnoredAny>,
+    /// Why a closed issue was closed, `completed` or `not_planned`.
+    #[serde(borrow)]
+    state_reason: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comment<'a> {
+    #[serde(borrow)]
+    user: Sender<'a>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+    #[serde(borrow)]
+    body: Cow<'a, str>,
+}
+
+#[derive(Template)]
+#[template(path = "issue_comment_event.html")]
+pub struct ViewIssueCommentEvent<'a> {
+    is_pull_request: bool,
+    number: u32,
+    issue_url: &'a str,
+    html_url: &'a str,
+    body: ViewSnippet<'a>,
+    repository: ViewRepository<'a>,
+    commenter: ViewSender<'a>,
+}
+
+impl ViewIssueCommentEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        let issue = if self.is_pull_request {
+            format!("PR#{}", self.number)
+        } else {
+            format!("issue #{}", self.number)
+        };
+        self.repository.embed(format!(
+            "{} {} on {}: {}",
+            self.commenter.to_markdown(),
+            discord::link("commented", self.html_url),
+            discord::link(&issue, self.issue_url),
+            discord::escape(self.body.text),
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssuesEvent<'a> {
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow)]
+    issue: Issue<'a>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl IssuesEvent<'_> {
+    /// Returns whether an issue was opened, closed or reopened.
+    pub fn should_announce(&self) -> bool {
+        matches!(&*self.action, "opened" | "closed" | "reopened")
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewIssuesEvent<'a> {
+        let (icon, action, action_color) = match (&*self.action, self.issue.state_reason.as_deref())
+        {
+            ("closed", Some("completed")) => ("\u{2713} ", "closed", "8250DF"),
+            ("closed", Some("not_planned")) => ("\u{2298} ", "closed as not planned", "6A737D"),
+            ("closed", _) => ("", "closed", "CB2431"),
+            (action, _) => ("", action, "28A745"),
+        };
+        let action = ctx
+            .action_verbs
+            .get("issues", &self.action)
+            .unwrap_or(action);
+        ViewIssuesEvent {
+            icon,
+            action,
+            action_color,
+            number: self.issue.number,
+            title: &self.issue.title,
+            html_url: &self.issue.html_url,
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "issue_event.html")]
+pub struct ViewIssuesEvent<'a> {
+    icon: &'static str,
+    action: &'a str,
+    action_color: &'static str,
+    number: u32,
+    title: &'a str,
+    html_url: &'a str,
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewIssuesEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} {}{} {}: {}",
+            self.sender.to_markdown(),
+            self.icon,
+            self.action,
+            discord::link(&format!("issue #{}", self.number), self.html_url),
+            discord::escape(self.title),
+        ))
+    }
+}
+
+/// A `star` event, or a legacy `watch` event (which despite its name is also
+/// sent when a repository is starred).
+#[derive(Debug, Deserialize)]
+pub struct StarEvent<'a> {
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl StarEvent<'_> {
+    /// Returns whether a star was added, and when `milestone` is set, whether
+    /// the star count is now a multiple of it.
+    // `u32::is_multiple_of` is newer than the minimum supported Rust version.
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    pub fn should_announce(&self, milestone: Option<u32>) -> bool {
+        let is_milestone = match milestone {
+            Some(milestone) if milestone != 0 => self.repository.stargazers_count % milestone == 0,
+            _ => true,
+        };
+        matches!(&*self.action, "created" | "started") && is_milestone
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewStarEvent<'a> {
+        ViewStarEvent {
+            stars: self.repository.stargazers_count,
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "star_event.html")]
+pub struct ViewStarEvent<'a> {
+    stars: u32,
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewStarEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} starred the repository, which now has {} stars",
+            self.sender.to_markdown(),
+            self.stars,
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForkEvent<'a> {
+    #[serde(borrow)]
+    forkee: Forkee<'a>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl ForkEvent<'_> {
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewForkEvent<'a> {
+        ViewForkEvent {
+            full_name: &self.forkee.full_name,
+            html_url: &self.forkee.html_url,
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Forkee<'a> {
+    #[serde(borrow)]
+    full_name: Cow<'a, str>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+}
+
+#[derive(Template)]
+#[template(path = "fork_event.html")]
+pub struct ViewForkEvent<'a> {
+    full_name: &'a str,
+    html_url: &'a str,
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewForkEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} forked the repository to {}",
+            self.sender.to_markdown(),
+            discord::link(&discord::escape(self.full_name), self.html_url),
+        ))
+    }
+}
+
+/// A `member` event, sent when a collaborator is added to or removed from a
+/// repository, or their permissions change.
+#[derive(Debug, Deserialize)]
+pub struct MemberEvent<'a> {
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow)]
+    member: Sender<'a>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl MemberEvent<'_> {
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewMemberEvent<'a> {
+        let (action, suffix) = match &*self.action {
+            "added" => ("added", " as a collaborator"),
+            "removed" => ("removed", " from collaborators"),
+            _ => ("changed permissions of", ""),
+        };
+        ViewMemberEvent {
+            action,
+            suffix,
+            member: self.member.to_view(ctx.username_aliases, &self.repository),
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "member_event.html")]
+pub struct ViewMemberEvent<'a> {
+    action: &'static str,
+    suffix: &'static str,
+    member: ViewSender<'a>,
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewMemberEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} {} {}{}",
+            self.sender.to_markdown(),
+            self.action,
+            self.member.to_markdown(),
+            self.suffix,
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscussionEvent<'a> {
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow)]
+    discussion: Discussion<'a>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl DiscussionEvent<'_> {
+    /// Returns whether a discussion was started or answered.
+    pub fn should_announce(&self) -> bool {
+        matches!(&*self.action, "created" | "answered")
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewDiscussionEvent<'a> {
+        let action = match &*self.action {
+            "answered" => "answered a discussion",
+            _ => "started a discussion",
+        };
+        ViewDiscussionEvent {
+            action,
+            title: &self.discussion.title,
+            html_url: &self.discussion.html_url,
+            category: &self.discussion.category.name,
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Discussion<'a> {
+    #[serde(borrow)]
+    title: Cow<'a, str>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+    #[serde(borrow)]
+    category: DiscussionCategory<'a>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscussionCategory<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+}
+
+#[derive(Template)]
+#[template(path = "discussion_event.html")]
+pub struct ViewDiscussionEvent<'a> {
+    action: &'static str,
+    title: &'a str,
+    html_url: &'a str,
+    category: &'a str,
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewDiscussionEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} {} in {}: {}",
+            self.sender.to_markdown(),
+            self.action,
+            discord::escape(self.category),
+            discord::link(&discord::escape(self.title), self.html_url),
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GollumEvent<'a> {
+    #[serde(borrow)]
+    pages: Vec<WikiPage<'a>>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl GollumEvent<'_> {
+    pub fn should_announce(&self) -> bool {
+        !self.pages.is_empty()
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewGollumEvent<'a> {
+        ViewGollumEvent {
+            pages: &self.pages,
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WikiPage<'a> {
+    #[serde(borrow)]
+    page_name: Cow<'a, str>,
+    #[serde(borrow)]
+    title: Cow<'a, str>,
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+}
+
+impl WikiPage<'_> {
+    /// The page title, or its name for pages without one.
+    fn display_title(&self) -> &str {
+        if self.title.is_empty() {
+            &self.page_name
+        } else {
+            &self.title
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "gollum_event.html")]
+pub struct ViewGollumEvent<'a> {
+    pages: &'a [WikiPage<'a>],
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewGollumEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        let pages: Vec<_> = self
+            .pages
+            .iter()
+            .map(|page| {
+                format!(
+                    "{} wiki page {}",
+                    page.action,
+                    discord::link(&discord::escape(page.display_title()), &page.html_url),
+                )
+            })
+            .collect();
+        self.repository.embed(format!(
+            "{} {}",
+            self.sender.to_markdown(),
+            pages.join(", "),
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RepositoryEvent<'a> {
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow, default)]
+    changes: RepositoryChanges<'a>,
+    #[serde(borrow)]
+    sender: Sender<'a>,
+    #[serde(borrow)]
+    pub repository: Repository<'a>,
+}
+
+impl RepositoryEvent<'_> {
+    /// Returns the previous default branch, if this event renamed it.
+    pub fn previous_default_branch(&self) -> Option<&str> {
+        match &self.changes.default_branch {
+            Some(change) if self.action == "edited" => Some(&change.from),
+            _ => None,
+        }
+    }
+
+    /// Returns the view of a default branch change, `None` for other changes.
+    pub fn to_default_branch_view<'a>(
+        &'a self,
+        ctx: ViewContext<'a>,
+    ) -> Option<ViewDefaultBranchEvent<'a>> {
+        Some(ViewDefaultBranchEvent {
+            from: self.previous_default_branch()?,
+            to: &self.repository.default_branch,
+            repository: self.repository.to_view(ctx),
+            sender: self.sender.to_view(ctx.username_aliases, &self.repository),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RepositoryChanges<'a> {
+    #[serde(borrow)]
+    default_branch: Option<Change<'a>>,
+}
+
+#[derive(Template)]
+#[template(path = "default_branch_event.html")]
+pub struct ViewDefaultBranchEvent<'a> {
+    from: &'a str,
+    to: &'a str,
+    repository: ViewRepository<'a>,
+    sender: ViewSender<'a>,
+}
+
+impl ViewDefaultBranchEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        self.repository.embed(format!(
+            "{} changed the default branch from `{}` to `{}`",
+            self.sender.to_markdown(),
+            self.from,
+            self.to,
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkflowRunEvent<'a> {
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow)]
+    workflow_run: WorkflowRun<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl WorkflowRunEvent<'_> {
+    /// Returns whether this is a finished run on the default branch of one of
+    /// the given workflows (or of any workflow when `None`).
+    pub fn should_announce(&self, workflows: Option<&HashSet<String>>) -> bool {
+        let is_listed = match workflows {
+            Some(workflows) => workflows.contains(&*self.workflow_run.name),
+            None => true,
+        };
+        self.action == "completed"
+            && self.workflow_run.head_branch == self.repository.default_branch
+            && is_listed
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewWorkflowRunEvent<'a> {
+        let conclusion = self
+            .workflow_run
+            .conclusion
+            .as_deref()
+            .unwrap_or("finished");
+        let (conclusion, conclusion_color) = match conclusion {
+            "success" => ("succeeded", Some("28A745")),
+            "failure" => ("failed", Some("CB2431")),
+            conclusion => (conclusion, None),
+        };
+        ViewWorkflowRunEvent {
+            name: &self.workflow_run.name,
+            html_url: &self.workflow_run.html_url,
+            conclusion,
+            conclusion_color,
+            repository: self.repository.to_view(ctx),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CheckRunEvent<'a> {
+    #[serde(borrow)]
+    action: Cow<'a, str>,
+    #[serde(borrow)]
+    check_run: CheckRun<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl CheckRunEvent<'_> {
+    /// Returns whether this is a finished check on the default branch with
+    /// one of the given names (or with any name when `None`).
+    pub fn should_announce(&self, check_runs: Option<&HashSet<String>>) -> bool {
+        let is_listed = match check_runs {
+            Some(check_runs) => check_runs.contains(&*self.check_run.name),
+            None => true,
+        };
+        self.action == "completed"
+            && self.check_run.check_suite.head_branch.as_deref()
+                == Some(&*self.repository.default_branch)
+            && is_listed
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewCheckRunEvent<'a> {
+        let conclusion = self.check_run.conclusion.as_deref().unwrap_or("finished");
+        let (conclusion, conclusion_color) = match conclusion {
+            "success" => ("succeeded", Some("28A745")),
+            "failure" => ("failed", Some("CB2431")),
+            "timed_out" => ("timed out", Some("CB2431")),
+            "cancelled" => ("was cancelled", Some("6A737D")),
+            conclusion => (conclusion, None),
+        };
+        ViewCheckRunEvent {
+            name: &self.check_run.name,
+            html_url: &self.check_run.html_url,
+            head_sha: short_id(&self.check_run.head_sha),
+            conclusion,
+            conclusion_color,
+            repository: self.repository.to_view(ctx),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckRun<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(borrow)]
+    conclusion: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+    #[serde(borrow)]
+    head_sha: Cow<'a, str>,
+    #[serde(borrow)]
+    check_suite: CheckSuite<'a>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckSuite<'a> {
+    #[serde(borrow)]
+    head_branch: Option<Cow<'a, str>>,
+}
+
+#[derive(Template)]
+#[template(path = "check_run_event.html")]
+pub struct ViewCheckRunEvent<'a> {
+    name: &'a str,
+    html_url: &'a str,
+    head_sha: &'a str,
+    conclusion: &'a str,
+    conclusion_color: Option<&'static str>,
+    repository: ViewRepository<'a>,
+}
+
+impl ViewCheckRunEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        let description = format!(
+            "{} on `{}` {}",
+            discord::link(&discord::escape(self.name), self.html_url),
+            self.head_sha,
+            self.conclusion,
+        );
+        match self.conclusion_color {
+            Some(color) => Embed::new(
+                format!("{} {}", self.repository.to_markdown(), description),
+                color,
+            ),
+            None => self.repository.embed(description),
+        }
+    }
+}
+
+/// A `status` event, sent by CI services using the commit status API
+/// rather than checks.
+#[derive(Debug, Deserialize)]
+pub struct StatusEvent<'a> {
+    #[serde(borrow)]
+    state: Cow<'a, str>,
+    #[serde(borrow)]
+    context: Cow<'a, str>,
+    #[serde(borrow)]
+    description: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    target_url: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    sha: Cow<'a, str>,
+    #[serde(borrow)]
+    branches: Vec<StatusBranch<'a>>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl StatusEvent<'_> {
+    /// Returns whether this is a final status of a commit on a branch
+    /// matching the `branch` glob pattern (or the default branch) with one of
+    /// the given contexts (or with any context when `None`).
+    pub fn should_announce(
+        &self,
+        contexts: Option<&HashSet<String>>,
+        branch: Option<&str>,
+    ) -> bool {
+        let is_listed = match contexts {
+            Some(contexts) => contexts.contains(&*self.context),
+            None => true,
+        };
+        let is_on_branch = |b: &StatusBranch<'_>| match branch {
+            Some(pattern) => glob::matches(pattern, &b.name),
+            None => b.name == self.repository.default_branch,
+        };
+        self.state != "pending" && self.branches.iter().any(is_on_branch) && is_listed
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewStatusEvent<'a> {
+        let (state, state_color) = match &*self.state {
+            "success" => ("succeeded", Some("28A745")),
+            "failure" => ("failed", Some("CB2431")),
+            "error" => ("errored", Some("CB2431")),
+            state => (state, None),
+        };
+        ViewStatusEvent {
+            context: &self.context,
+            description: self.description.as_deref().filter(|d| !d.is_empty()),
+            target_url: self.target_url.as_deref(),
+            sha: short_id(&self.sha),
+            state,
+            state_color,
+            repository: self.repository.to_view(ctx),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusBranch<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+}
+
+#[derive(Template)]
+#[template(path = "status_event.html")]
+pub struct ViewStatusEvent<'a> {
+    context: &'a str,
+    description: Option<&'a str>,
+    target_url: Option<&'a str>,
+    sha: &'a str,
+    state: &'a str,
+    state_color: Option<&'static str>,
+    repository: ViewRepository<'a>,
+}
+
+impl ViewStatusEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        let context = discord::escape(self.context);
+        let mut description = format!(
+            "{} on `{}` {}",
+            match self.target_url {
+                Some(target_url) => discord::link(&context, target_url),
+                None => context,
+            },
+            self.sha,
+            self.state,
+        );
+        if let Some(status_description) = self.description {
+            description += &format!(": {}", discord::escape(status_description));
+        }
+        match self.state_color {
+            Some(color) => Embed::new(
+                format!("{} {}", self.repository.to_markdown(), description),
+                color,
+            ),
+            None => self.repository.embed(description),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkflowRun<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(borrow)]
+    conclusion: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    html_url: Cow<'a, str>,
+    #[serde(borrow)]
+    head_branch: Cow<'a, str>,
+}
+
+#[derive(Template)]
+#[template(path = "workflow_run_event.html")]
+pub struct ViewWorkflowRunEvent<'a> {
+    name: &'a str,
+    html_url: &'a str,
+    conclusion: &'a str,
+    conclusion_color: Option<&'static str>,
+    repository: ViewRepository<'a>,
+}
+
+impl ViewWorkflowRunEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        let description = format!(
+            "{} {}",
+            discord::link(&discord::escape(self.name), self.html_url),
+            self.conclusion,
+        );
+        match self.conclusion_color {
+            Some(color) => Embed::new(
+                format!("{} {}", self.repository.to_markdown(), description),
+                color,
+            ),
+            None => self.repository.embed(description),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeploymentStatusEvent<'a> {
+    #[serde(borrow)]
+    deployment_status: DeploymentStatus<'a>,
+    #[serde(borrow)]
+    deployment: Deployment<'a>,
+    #[serde(borrow)]
+    repository: Repository<'a>,
+}
+
+impl DeploymentStatusEvent<'_> {
+    /// Returns whether a deployment to one of the given environments (or to
+    /// any environment when `None`) finished.
+    pub fn should_announce(&self, environments: Option<&HashSet<String>>) -> bool {
+        let is_listed = match environments {
+            Some(environments) => environments.contains(&*self.deployment_status.environment),
+            None => true,
+        };
+        matches!(
+            &*self.deployment_status.state,
+            "success" | "failure" | "error"
+        ) && is_listed
+    }
+
+    pub fn to_view<'a>(&'a self, ctx: ViewContext<'a>) -> ViewDeploymentStatusEvent<'a> {
+        let (state, state_color) = match &*self.deployment_status.state {
+            "success" => ("succeeded", "28A745"),
+            "failure" => ("failed", "CB2431"),
+            "error" => ("errored", "CB2431"),
+            state => (state, "909090"),
+        };
+        ViewDeploymentStatusEvent {
+            environment: &self.deployment_status.environment,
+            target_url: self
+                .deployment_status
+                .target_url
+                .as_deref()
+                .filter(|url| !url.is_empty()),
+            git_ref: &self.deployment.git_ref,
+            state,
+            state_color,
+            repository: self.repository.to_view(ctx),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeploymentStatus<'a> {
+    #[serde(borrow)]
+    state: Cow<'a, str>,
+    #[serde(borrow)]
+    environment: Cow<'a, str>,
+    #[serde(borrow)]
+    target_url: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Deployment<'a> {
+    #[serde(borrow, rename = "ref")]
+    git_ref: Cow<'a, str>,
+}
+
+#[derive(Template)]
+#[template(path = "deployment_status_event.html")]
+pub struct ViewDeploymentStatusEvent<'a> {
+    environment: &'a str,
+    target_url: Option<&'a str>,
+    git_ref: &'a str,
+    state: &'a str,
+    state_color: &'static str,
+    repository: ViewRepository<'a>,
+}
+
+impl ViewDeploymentStatusEvent<'_> {
+    pub fn to_discord(&self) -> Embed {
+        let deployment = match self.target_url {
+            Some(target_url) => discord::link("deployment", target_url),
+            None => "deployment".into(),
+        };
+        Embed::new(
+            format!(
+                "{} {} of `{}` to {} {}",
+                self.repository.to_markdown(),
+                deployment,
+                self.git_ref,
+                discord::escape(self.environment),
+                self.state,
+            ),
+            self.state_color,
+        )
+    }
+}
+
+#[derive(Template)]
+#[template(path = "snippet.html")]
+struct ViewSnippet<'a> {
+    text: &'a str,
+    full_text: &'a str,
+}
+
+impl<'a> ViewSnippet<'a> {
+    fn new(full_text: &'a str, max_length: usize) -> Self {
+        Self {
+            text: truncate(first_line(full_text), max_length),
+            full_text,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Sender<'a> {
+    #[serde(borrow)]
+    login: Cow<'a, str>,
+    /// Profile URL, which isn't on github.com for GitHub Enterprise.
+    #[serde(borrow, default)]
+    html_url: Option<Cow<'a, str>>,
+}
+
+impl Sender<'_> {
+    fn to_view<'a>(
+        &'a self,
+        username_aliases: &'a UsernameAliases,
+        repository: &Repository,
+    ) -> ViewSender<'a> {
+        ViewSender {
+            html_url: match &self.html_url {
+                Some(html_url) => Cow::Borrowed(html_url),
+                None => Cow::Owned(repository.profile_url(&self.login)),
+            },
+            renamed_login: username_aliases.get(&self.login),
+        }
+    }
+}
+
+struct ViewSender<'a> {
+    html_url: Cow<'a, str>,
+    renamed_login: &'a str,
+}
+
+impl ViewSender<'_> {
+    fn to_markdown(&self) -> String {
+        discord::link(&discord::escape(self.renamed_login), &self.html_url)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        gravatar_url, preview, truncate, Author, Base, Change, Changes, CheckRun, CheckRunEvent,
+        CheckSuite, Comment, Commit, CommitComment, CommitCommentEvent, Deployment,
+        DeploymentStatus, DeploymentStatusEvent, Discussion, DiscussionCategory, DiscussionEvent,
+        ForkEvent, Forkee, GollumEvent, Issue, IssueCommentEvent, IssuesEvent, Label, MemberEvent,
+        PullRequest, PullRequestEvent, PullRequestReviewEvent, PushEvent, PushEventContext, Pusher,
+        Repository, RepositoryChanges, RepositoryEvent, Review, Sender, StarEvent, StatusBranch,
+        StatusEvent, Username, Verification, ViewContext, WikiPage, WorkflowRun, WorkflowRunEvent,
+    };
+    use crate::config::{ActionVerbs, CommitTimestamps, UsernameAliases};
+    use crate::github_api::{ClientOptions, GitHubApi, User};
+    use crate::template_overrides::TemplateOverrides;
+    use once_cell::sync::Lazy;
+    use serde::de::IgnoredAny;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::{env, fs, process};
+    use warp::Filter;
+
+    static NO_REPOSITORY_ALIASES: Lazy<HashMap<String, String>> = Lazy::new(HashMap::new);
+    static NO_ACTION_VERBS: Lazy<ActionVerbs> = Lazy::new(ActionVerbs::default);
+
+    fn view_context(username_aliases: &UsernameAliases) -> ViewContext<'_> {
+        ViewContext {
+            username_aliases,
+            repository_aliases: &NO_REPOSITORY_ALIASES,
+            action_verbs: &NO_ACTION_VERBS,
+            showdown_repository_names: true,
+            emoji_shortcodes: false,
+            max_message_length: 120,
+            pull_request_preview_length: 200,
+            show_avatars: true,
+            repository_color: "FF00FF",
+        }
+    }
+
+    fn push_context(view: ViewContext<'_>) -> PushEventContext<'_> {
+        PushEventContext {
+            github_api: None,
+            view,
+            hide_merge_commits: false,
+            resolve_commit_authors: false,
+            enrich_authors: true,
+            group_commits: false,
+            commit_stats: false,
+            show_default_branch: false,
+            commit_timestamps: CommitTimestamps::Hidden,
+            gravatars: false,
+            repository_summary: false,
+        }
+    }
+
+    fn sample_repository() -> Repository<'static> {
+        Repository {
+            name: "ExampleCom".into(),
+            full_name: "Example/ExampleCom".into(),
+            html_url: "http://example.com/".into(),
+            default_branch: "master".into(),
+            stargazers_count: 99,
+        }
+    }
+
+    fn sample_commit() -> Commit<'static> {
+        Commit {
+            id: "0da2590a700d054fc2ce39ddc9c95f360329d9be".into(),
+            message: "Hello, world!".into(),
+            author: Author {
+                name: "Konrad Borowski".into(),
+                email: None,
+                username: Some("xfix".into()),
+            },
+            url: "http://example.com".into(),
+            verification: None,
+            added: vec![],
+            modified: vec![],
+            removed: vec![],
+            timestamp: None,
+        }
+    }
+
+    fn sample_sender() -> Sender<'static> {
+        Sender {
+            login: "alice".into(),
+            html_url: None,
+        }
+    }
+
+    #[test]
+    fn test_sender_url() {
+        let username_aliases = UsernameAliases::default();
+        let repository = Repository {
+            html_url: "https://git.example.com/Example/ExampleCom".into(),
+            ..sample_repository()
+        };
+        let mut sender = sample_sender();
+        assert_eq!(
+            sender.to_view(&username_aliases, &repository).html_url,
+            "https://git.example.com/alice",
+        );
+        sender.html_url = Some("https://git.example.com/alice".into());
+        let view = sender.to_view(&username_aliases, &repository);
+        assert_eq!(view.html_url, "https://git.example.com/alice");
+        assert_eq!(view.to_markdown(), "[alice](https://git.example.com/alice)");
+    }
+
+    fn sample_push_event(git_ref: &'static str) -> PushEvent<'static> {
+        PushEvent {
+            git_ref: git_ref.into(),
+            commits: vec![sample_commit()],
+            deleted: false,
+            compare: None,
+            pusher: Some(Pusher {
+                name: "xfix".into(),
+                email: Some("konrad@borowski.pw".into()),
+            }),
+            sender: sample_sender(),
+            repository: sample_repository(),
+        }
+    }
+
+    #[test]
+    fn test_branch_push() {
+        let push_event = sample_push_event("refs/heads/feature/tags");
+        assert!(!push_event.is_tag());
+        assert!(!push_event.is_release_tag());
+        assert_eq!(push_event.branch(), Some("feature/tags"));
+    }
+
+    #[test]
+    fn test_tag_push() {
+        let push_event = sample_push_event("refs/tags/v1.2.3");
+        assert!(push_event.is_tag());
+        assert!(push_event.is_release_tag());
+        assert_eq!(push_event.branch(), None);
+        assert_eq!(
+            push_event
+                .to_tag_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/alice'><font color='909090'>alice</font></a> ",
+                "tagged <b>🏷 <a href='http://example.com/releases/tag/v1.2.3'>v1.2.3</a></b>",
+            ),
+        );
+        assert!(sample_push_event("refs/tags/2.0.0-beta.1+build.5").is_release_tag());
+        assert!(!sample_push_event("refs/tags/nightly").is_release_tag());
+        let deleted = PushEvent {
+            deleted: true,
+            ..sample_push_event("refs/tags/v1.2.3")
+        };
+        assert!(!deleted.is_release_tag());
+    }
+
+    #[test]
+    fn test_push_event_discord() {
+        let push_event = PushEvent {
+            commits: vec![Commit {
+                message: "Fix *all* the things\n\nDetails".into(),
+                ..sample_commit()
+            }],
+            ..sample_push_event("refs/heads/master")
+        };
+        assert_eq!(
+            push_event
+                .to_discord(view_context(&UsernameAliases::default()), false)
+                .description,
+            concat!(
+                r"**[\[ExampleCom\]](http://example.com/)**",
+                "\n[`0da259`](http://example.com) ",
+                r"Fix \*all\* the things (xfix)",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_push_event_text() {
+        let push_event = PushEvent {
+            commits: vec![
+                sample_commit(),
+                Commit {
+                    message: "<b>Not HTML</b>\n\nDetails".into(),
+                    author: Author {
+                        name: "Anonymous".into(),
+                        email: None,
+                        username: None,
+                    },
+                    ..sample_commit()
+                },
+            ],
+            ..sample_push_event("refs/heads/master")
+        };
+        assert_eq!(
+            push_event.to_text(view_context(&UsernameAliases::default()), false),
+            [
+                "[ExampleCom] 0da259 Hello, world! (xfix) http://example.com",
+                "[ExampleCom] 0da259 <b>Not HTML</b> (Anonymous) http://example.com",
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_event_template_override() {
+        let directory = env::temp_dir().join(format!("psdevbot-push-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("push_event.html"),
+            "<b>{{ repository }}</b> {{ commit }}",
+        )
+        .unwrap();
+        let overrides = TemplateOverrides::load(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+        let overrides = overrides.unwrap();
+        let username_aliases = UsernameAliases::default();
+        let push_event = sample_push_event("refs/heads/master");
+        let view = push_event
+            .to_simple_view(push_context(view_context(&username_aliases)))
+            .await;
+        let commit = concat!(
+            "<a href='http://example.com' title='Hello, world!'>Hello, world!</a> ",
+            "<font color=909090>",
+            r#"<font color=909090 title="Konrad Borowski">(xfix)</font></font>"#,
+        );
+        assert_eq!(
+            view.render(&overrides),
+            format!("<b>{0}</b> {1}", view.repository, commit),
+        );
+        assert_eq!(view.render(&TemplateOverrides::default()), view.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_pusher_differs_from_author() {
+        let mut username_aliases = UsernameAliases::default();
+        username_aliases.insert("alice".into(), "Alice".into());
+        let push_event = PushEvent {
+            pusher: Some(Pusher {
+                name: "alice".into(),
+                email: Some("alice@example.com".into()),
+            }),
+            ..sample_push_event("refs/heads/master")
+        };
+        let html = push_event
+            .to_simple_view(push_context(view_context(&username_aliases)))
+            .await
+            .to_string();
+        assert_eq!(
+            html,
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/alice'><font color='909090' ",
+                "title='alice@example.com'>Alice</font></a> pushed 1 commit<br>",
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com' title='Hello, world!'>Hello, world!</a> ",
+                "<font color=909090><font color=909090 title=\"Konrad Borowski\">",
+                "(xfix)</font></font>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_pusher_url_on_repository_host() {
+        let username_aliases = UsernameAliases::default();
+        let push_event = PushEvent {
+            pusher: Some(Pusher {
+                name: "carol".into(),
+                email: None,
+            }),
+            repository: Repository {
+                html_url: "https://git.example.com/Example/ExampleCom".into(),
+                ..sample_repository()
+            },
+            ..sample_push_event("refs/heads/master")
+        };
+        let pusher = push_event.to_pusher_view(&username_aliases, false).unwrap();
+        assert_eq!(pusher.html_url, "https://git.example.com/carol");
+    }
+
+    #[tokio::test]
+    async fn test_push_to_other_branch() {
+        let username_aliases = UsernameAliases::default();
+        let repository =
+            "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ";
+        let cases = [
+            ("refs/heads/develop", false, Some("develop")),
+            ("refs/heads/master", false, None),
+            ("refs/heads/master", true, Some("master")),
+        ];
+        for (git_ref, show_default_branch, branch) in cases {
+            let push_event = PushEvent {
+                pusher: None,
+                ..sample_push_event(git_ref)
+            };
+            let html = push_event
+                .to_simple_view(PushEventContext {
+                    show_default_branch,
+                    ..push_context(view_context(&username_aliases))
+                })
+                .await
+                .to_string();
+            let header =
+                branch.map(|branch| format!("{}Pushed to <b>{}</b><br>", repository, branch));
+            assert_eq!(
+                html.strip_prefix(&header.unwrap_or_default()).unwrap(),
+                concat!(
+                    "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                    "<a href='http://example.com' title='Hello, world!'>Hello, world!</a> ",
+                    "<font color=909090><font color=909090 title=\"Konrad Borowski\">",
+                    "(xfix)</font></font>",
+                ),
+            );
+        }
+        let push_event = PushEvent {
+            pusher: Some(Pusher {
+                name: "alice".into(),
+                email: None,
+            }),
+            ..sample_push_event("refs/heads/develop")
+        };
+        let html = push_event
+            .to_simple_view(push_context(view_context(&username_aliases)))
+            .await
+            .to_string();
+        assert!(html.contains(">alice</font></a> pushed 1 commit to <b>develop</b><br>"));
+    }
+
+    #[tokio::test]
+    async fn test_long_push_is_truncated() {
+        let commits = (0..50)
+            .map(|_| Commit {
+                message: "A very long commit message that goes on and on".into(),
+                ..sample_commit()
+            })
+            .collect();
+        let push_event = PushEvent {
+            commits,
+            compare: Some("https://github.com/Example/ExampleCom/compare/a...b".into()),
+            ..sample_push_event("refs/heads/master")
+        };
+        let username_aliases = UsernameAliases::default();
+        let view = push_event
+            .to_simple_view(push_context(view_context(&username_aliases)))
+            .await;
+        let overrides = TemplateOverrides::default();
+        let full = view.render(&overrides);
+        let html = view.render_within(&overrides, 2000);
+        assert!(full.len() > 2000);
+        assert!(html.len() <= 2000);
+        let kept = html
+            .strip_suffix(concat!(
+                "<br><a href='https://github.com/Example/ExampleCom/compare/a...b'>",
+                "&hellip;and more</a>",
+            ))
+            .unwrap();
+        assert!(full.starts_with(kept));
+        assert!(full.len() > kept.len());
+    }
+
+    #[tokio::test]
+    async fn test_short_push_is_not_truncated() {
+        let push_event = sample_push_event("refs/heads/master");
+        let username_aliases = UsernameAliases::default();
+        let view = push_event
+            .to_simple_view(push_context(view_context(&username_aliases)))
+            .await;
+        let overrides = TemplateOverrides::default();
+        let full = view.render(&overrides);
+        assert_eq!(view.render_within(&overrides, full.len()), full);
+    }
+
+    #[tokio::test]
+    async fn test_authors_not_enriched() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path("users").map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply::json(&serde_json::json!({
+                "html_url": "https://github.com/xfix",
+                "avatar_url": "https://avatars.githubusercontent.com/u/1",
+            }))
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        let username_aliases = UsernameAliases::default();
+        let push_event = sample_push_event("refs/heads/master");
+        for enrich_authors in [false, true] {
+            let mut ctx = PushEventContext {
+                github_api: Some(&mut github_api),
+                resolve_commit_authors: true,
+                enrich_authors,
+                ..push_context(view_context(&username_aliases))
+            };
+            push_event.prefetch_authors(&mut ctx).await;
+            let html = push_event.to_view(ctx).await.to_string();
+            assert_eq!(html.contains("https://github.com/xfix"), enrich_authors);
+            assert_eq!(requests.load(Ordering::SeqCst), enrich_authors as usize);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repository_summary() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let route = warp::path!("repos" / "Example" / "ExampleCom").map(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            warp::reply::json(&serde_json::json!({
+                "default_branch": "master",
+                "description": "An example",
+                "stargazers_count": 99,
+            }))
+        });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        let username_aliases = UsernameAliases::default();
+        let push_event = sample_push_event("refs/heads/master");
+        for repository_summary in [false, true] {
+            let html = push_event
+                .to_view(PushEventContext {
+                    github_api: Some(&mut github_api),
+                    repository_summary,
+                    ..push_context(view_context(&username_aliases))
+                })
+                .await
+                .to_string();
+            assert_eq!(
+                html.contains("title='An example (99 stars)'"),
+                repository_summary,
+            );
+            assert_eq!(requests.load(Ordering::SeqCst), repository_summary as usize);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_stats() {
+        let route = warp::path!("repos" / "Example" / "ExampleCom" / "commits" / String).map(
+            |sha: String| {
+                warp::reply::json(&serde_json::json!({
+                    "sha": sha,
+                    "author": { "login": "xfix" },
+                    "stats": { "total": 52, "additions": 40, "deletions": 12 },
+                }))
+            },
+        );
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let mut github_api = GitHubApi::with_base_url(
+            "user".into(),
+            "password".into(),
+            format!("http://{}", address),
+            ClientOptions::default(),
+        );
+        let username_aliases = UsernameAliases::default();
+        for commit_stats in [false, true] {
+            let html = sample_commit()
+                .to_view(
+                    "Example/ExampleCom",
+                    "http://example.com/",
+                    &mut PushEventContext {
+                        github_api: Some(&mut github_api),
+                        enrich_authors: false,
+                        commit_stats,
+                        ..push_context(view_context(&username_aliases))
+                    },
+                )
+                .await
+                .to_string();
+            let stats = concat!(
+                "</span> <font color=28A745>+40</font> ",
+                "<font color=D73A49>&minus;12</font> ",
+            );
+            assert_eq!(html.contains(stats), commit_stats);
+        }
+    }
+
+    #[test]
+    fn test_gravatar_url() {
+        assert_eq!(
+            gravatar_url(" MyEmailAddress@example.com "),
+            concat!(
+                "https://www.gravatar.com/avatar/",
+                "84059b07d4be67b806386c0aad8070a23f18836bbaae342275dc0a83414c32ee",
+                "?s=32&d=identicon",
+            ),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gravatar() {
+        let commit = Commit {
+            author: Author {
+                name: "Konrad Borowski".into(),
+                email: Some("MyEmailAddress@example.com".into()),
+                username: None,
+            },
+            ..sample_commit()
+        };
+        let username_aliases = UsernameAliases::default();
+        for gravatars in [false, true] {
+            let html = commit
+                .to_view(
+                    "Example/ExampleCom",
+                    "http://example.com/",
+                    &mut PushEventContext {
+                        enrich_authors: false,
+                        gravatars,
+                        ..push_context(view_context(&username_aliases))
+                    },
+                )
+                .await
+                .to_string();
+            let image = concat!(
+                "<font color=909090>(<img src='https://www.gravatar.com/avatar/",
+                "84059b07d4be67b806386c0aad8070a23f18836bbaae342275dc0a83414c32ee",
+                "?s=32&amp;d=identicon' height=16 alt=''>Konrad Borowski)</font>",
+            );
+            assert_eq!(html.contains(image), gravatars, "{}", html);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_timestamp() {
+        let commit = Commit {
+            timestamp: Some("2021-05-01T12:00:00+02:00".into()),
+            ..sample_commit()
+        };
+        let username_aliases = UsernameAliases::default();
+        for (commit_timestamps, title) in [
+            (CommitTimestamps::Hidden, None),
+            (CommitTimestamps::Absolute, Some("2021-05-01 10:00 UTC")),
+        ] {
+            let html = commit
+                .to_view(
+                    "Example/ExampleCom",
+                    "http://example.com/",
+                    &mut PushEventContext {
+                        enrich_authors: false,
+                        commit_timestamps,
+                        ..push_context(view_context(&username_aliases))
+                    },
+                )
+                .await
+                .to_string();
+            let link = match title {
+                Some(title) => format!("<a href='http://example.com' title='{}'>", title),
+                None => "<a href='http://example.com'>".into(),
+            };
+            assert!(html.starts_with(&link), "{}", html);
+        }
+        let relative = commit.format_timestamp(CommitTimestamps::Relative).unwrap();
+        assert!(relative.ends_with("d ago"), "{}", relative);
+    }
+
+    fn commit_by(message: &'static str, username: &'static str) -> Commit<'static> {
+        Commit {
+            message: message.into(),
+            author: Author {
+                name: username.into(),
+                email: None,
+                username: Some(username.into()),
+            },
+            ..sample_commit()
+        }
+    }
+
+    async fn grouped_simple_view(commits: Vec<Commit<'static>>) -> String {
+        let push_event = PushEvent {
+            commits,
+            pusher: None,
+            ..sample_push_event("refs/heads/master")
+        };
+        push_event
+            .to_simple_view(PushEventContext {
+                group_commits: true,
+                ..push_context(view_context(&UsernameAliases::default()))
+            })
+            .await
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_grouping_alternating_authors() {
+        let html = grouped_simple_view(vec![
+            commit_by("First", "alice"),
+            commit_by("Second", "bob"),
+            commit_by("Third", "alice"),
+        ])
+        .await;
+        let repository = "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>]";
+        let header = |name| {
+            format!(
+                r#"{} <font color=909090 title="{1}">({1})</font>:"#,
+                repository, name
+            )
+        };
+        let commit =
+            |message| format!("<a href='http://example.com' title='{0}'>{0}</a>", message,);
+        assert_eq!(
+            html,
+            [
+                header("alice"),
+                commit("First"),
+                header("bob"),
+                commit("Second"),
+                header("alice"),
+                commit("Third"),
+            ]
+            .join("<br>"),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_grouping_consecutive_commits() {
+        let html = grouped_simple_view(vec![
+            commit_by("First", "alice"),
+            commit_by("Second", "alice"),
+            commit_by("Third", "alice"),
+        ])
+        .await;
+        assert_eq!(
+            html,
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                r#"<font color=909090 title="alice">(alice)</font>:"#,
+                "<br><a href='http://example.com' title='First'>First</a>",
+                "<br><a href='http://example.com' title='Second'>Second</a>",
+                "<br><a href='http://example.com' title='Third'>Third</a>",
+            ),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_event_hiding_merge_commits() {
+        let push_event = PushEvent {
+            commits: vec![
+                Commit {
+                    message: "Merge pull request #1 from xfix/branch".into(),
+                    ..sample_commit()
+                },
+                sample_commit(),
+            ],
+            ..sample_push_event("refs/heads/master")
+        };
+        assert!(push_event.has_visible_commits(true));
+        assert_eq!(
+            push_event
+                .to_simple_view(PushEventContext {
+                    hide_merge_commits: true,
+                    ..push_context(view_context(&UsernameAliases::default()))
+                })
+                .await
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com' title='Hello, world!'>Hello, world!</a> ",
+                "<font color=909090><font color=909090 title=\"Konrad Borowski\">",
+                "(xfix)</font></font>",
+            ),
+        );
+        let merges_only = PushEvent {
+            commits: vec![Commit {
+                message: "Merge branch 'master' into feature".into(),
+                ..sample_commit()
+            }],
+            ..push_event
+        };
+        assert!(merges_only.has_visible_commits(false));
+        assert!(!merges_only.has_visible_commits(true));
+    }
+
+    #[test]
+    fn test_retain_paths() {
+        let mut push_event = PushEvent {
+            commits: vec![
+                Commit {
+                    message: "Update the client".into(),
+                    modified: vec!["client/index.html".into()],
+                    ..sample_commit()
+                },
+                Commit {
+                    message: "Add a server module".into(),
+                    added: vec!["server/chat.ts".into()],
+                    ..sample_commit()
+                },
+                Commit {
+                    message: "Remove old client code".into(),
+                    modified: vec!["README.md".into()],
+                    removed: vec!["client/old.js".into()],
+                    ..sample_commit()
+                },
+            ],
+            ..sample_push_event("refs/heads/master")
+        };
+        push_event.retain_paths(&["client/".into()]);
+        let messages: Vec<_> = push_event
+            .commits
+            .iter()
+            .map(|commit| &*commit.message)
+            .collect();
+        assert_eq!(messages, ["Update the client", "Remove old client code"]);
+        push_event.retain_paths(&["data/".into(), "server/".into()]);
+        assert!(!push_event.has_visible_commits(false));
+    }
+
+    #[tokio::test]
+    async fn test_changed_files() {
+        let push_event = PushEvent {
+            commits: vec![
+                Commit {
+                    added: vec!["data/mods/gen9/moves.ts".into()],
+                    modified: vec!["data/moves.ts".into(), "sim/battle.ts".into()],
+                    ..sample_commit()
+                },
+                Commit {
+                    modified: vec!["data/moves.ts".into(), "data/mods/gen9/moves.ts".into()],
+                    removed: vec!["sim/old.ts".into()],
+                    ..sample_commit()
+                },
+            ],
+            pusher: Some(Pusher {
+                name: "alice".into(),
+                email: None,
+            }),
+            ..sample_push_event("refs/heads/master")
+        };
+        assert_eq!(push_event.changed_files(false), 4);
+        let html = push_event
+            .to_simple_view(push_context(view_context(&UsernameAliases::default())))
+            .await
+            .to_string();
+        assert!(html.contains(">alice</font></a> pushed 2 commits, 4 files changed<br>"));
+    }
+
+    #[tokio::test]
+    async fn test_commit() {
+        assert_eq!(
+            sample_commit()
+                .to_view(
+                    "Example/ExampleCom",
+                    "shouldn't be used",
+                    &mut push_context(view_context(&UsernameAliases::default()))
+                )
+                .await
+                .to_string(),
+            concat!(
+                "<a href='http://example.com'>",
                 "<font color=606060><kbd>0da259</kbd></font></a>\n",
                 "<span title='Hello, world!'>Hello, world!</span> ",
                 r#"<font color=909090 title="Konrad Borowski">(xfix)</font>"#,
@@ -355,6 +2789,770 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn test_commit_emoji_shortcodes() {
+        let commit = Commit {
+            message: ":sparkles: Add <b>bold</b> #12 :not_an_emoji:".into(),
+            ..sample_commit()
+        };
+        let username_aliases = UsernameAliases::default();
+        for emoji_shortcodes in [false, true] {
+            let html = commit
+                .to_view(
+                    "Example/ExampleCom",
+                    "http://example.com",
+                    &mut push_context(ViewContext {
+                        emoji_shortcodes,
+                        ..view_context(&username_aliases)
+                    }),
+                )
+                .await
+                .to_string();
+            let sparkles = if emoji_shortcodes {
+                "\u{2728}"
+            } else {
+                ":sparkles:"
+            };
+            let message = format!(
+                concat!(
+                    ">{} Add &lt;b&gt;bold&lt;/b&gt; ",
+                    "<a href='http://example.com/issues/12'>#12</a> :not_an_emoji:</span>",
+                ),
+                sparkles,
+            );
+            assert!(html.contains(&message), "{}", html);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verified_commit() {
+        let commit = Commit {
+            verification: Some(Verification { verified: true }),
+            ..sample_commit()
+        };
+        let username_aliases = UsernameAliases::default();
+        let mut ctx = push_context(view_context(&username_aliases));
+        assert!(commit
+            .to_view("Example/ExampleCom", "", &mut ctx)
+            .await
+            .to_string()
+            .starts_with(concat!(
+                "<a href='http://example.com'><font color=606060><kbd>0da259</kbd></font></a> ",
+                "<font color=28A745 title='Verified signature'>&#10003;</font>\n",
+            )));
+        let commit = Commit {
+            verification: Some(Verification { verified: false }),
+            ..sample_commit()
+        };
+        assert!(!commit
+            .to_view("Example/ExampleCom", "", &mut ctx)
+            .await
+            .to_string()
+            .contains("Verified"));
+    }
+
+    #[tokio::test]
+    async fn test_revert_commit() {
+        let revert = Commit {
+            message: "Revert \"Hello, world!\"\n\nThis reverts commit 0da2590.".into(),
+            ..sample_commit()
+        };
+        let username_aliases = UsernameAliases::default();
+        let mut ctx = push_context(view_context(&username_aliases));
+        let marker = "<font color=D73A49 title='Revert'>&#8617;</font> <span";
+        assert!(revert
+            .to_view("Example/ExampleCom", "", &mut ctx)
+            .await
+            .to_string()
+            .contains(marker));
+        let normal = Commit {
+            message: "Reverting is hard".into(),
+            ..sample_commit()
+        };
+        assert!(!normal
+            .to_view("Example/ExampleCom", "", &mut ctx)
+            .await
+            .to_string()
+            .contains("Revert'"));
+    }
+
+    #[tokio::test]
+    async fn test_commit_with_long_message() {
+        let commit = Commit {
+            message: "Zażółć gęślą jaźń\n\nLonger description".into(),
+            ..sample_commit()
+        };
+        assert_eq!(
+            commit
+                .to_simple_view(
+                    "Example/ExampleCom",
+                    &mut push_context(ViewContext {
+                        max_message_length: 6,
+                        ..view_context(&UsernameAliases::default())
+                    }),
+                )
+                .await
+                .to_string(),
+            concat!(
+                "<a href='http://example.com' title='Zażółć gęślą jaźń&#10;&#10;Longer description'>",
+                "Zażółć&hellip;</a> <font color=909090>",
+                r#"<font color=909090 title="Konrad Borowski">(xfix)</font></font>"#,
+            ),
+        );
+    }
+
+    #[test]
+    fn test_short_id() {
+        assert_eq!(sample_commit().short_id(), "0da259");
+        let mut commit = sample_commit();
+        commit.id = "abc".into();
+        assert_eq!(commit.short_id(), "abc");
+        commit.id = "żółwiątko".into();
+        assert_eq!(commit.short_id(), "żółwią");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Hello, world!", 13), "Hello, world!");
+        assert_eq!(truncate("Hello, world!", 6), "Hello,");
+        assert_eq!(truncate("Hello, world!", 7), "Hello,");
+        assert_eq!(truncate("żółw", 2), "żó");
+    }
+
+    #[test]
+    fn test_username_with_avatar() {
+        let user = User {
+            html_url: "https://github.com/xfix".into(),
+            avatar_url: "https://avatars.githubusercontent.com/u/1297598".into(),
+        };
+        assert_eq!(
+            Username {
+                username: "xfix".into(),
+                github_metadata: Some(&user),
+                show_avatar: true,
+            }
+            .to_string(),
+            concat!(
+                "<a href='https://github.com/xfix'>",
+                "<img src='https://avatars.githubusercontent.com/u/1297598' height=16 alt=''>",
+                "<font color=909090>xfix</font></a>",
+            ),
+        );
+        assert_eq!(
+            Username {
+                username: "xfix".into(),
+                github_metadata: Some(&user),
+                show_avatar: false,
+            }
+            .to_string(),
+            "<a href='https://github.com/xfix'><font color=909090>xfix</font></a>",
+        );
+        assert_eq!(
+            Username {
+                username: "xfix".into(),
+                github_metadata: None,
+                show_avatar: true,
+            }
+            .to_string(),
+            "xfix",
+        );
+    }
+
+    #[test]
+    fn test_commit_comment() {
+        let mut aliases = UsernameAliases::default();
+        aliases.insert("bob".into(), "Bob".into());
+        assert_eq!(
+            CommitCommentEvent {
+                action: "created".into(),
+                comment: CommitComment {
+                    user: Sender {
+                        login: "bob".into(),
+                        html_url: None,
+                    },
+                    commit_id: "0da2590a700d054fc2ce39ddc9c95f360329d9be".into(),
+                    html_url: "http://example.com/commit#comment".into(),
+                    body: "This <breaks> everything\nPlease fix".into(),
+                },
+                repository: sample_repository(),
+            }
+            .to_view(view_context(&aliases))
+            .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/bob'><font ",
+                "color='909090'>Bob</font></a> commented on ",
+                "<a href='http://example.com/commit#comment'>commit <kbd>0da259</kbd></a>: ",
+                "<span title='This &lt;breaks&gt; everything&#10;Please fix'>",
+                "This &lt;breaks&gt; everything&hellip;</span>",
+            ),
+        );
+    }
+
+    fn sample_issue_comment(pull_request: Option<IgnoredAny>) -> IssueCommentEvent<'static> {
+        IssueCommentEvent {
+            action: "created".into(),
+            issue: Issue {
+                number: 12,
+                title: "Crash on start".into(),
+                html_url: "http://example.com/issues/12".into(),
+                pull_request,
+                state_reason: None,
+            },
+            comment: Comment {
+                user: Sender {
+                    login: "alice".into(),
+                    html_url: None,
+                },
+                html_url: "http://example.com/issues/12#comment".into(),
+                body: "Looks good".into(),
+            },
+            repository: sample_repository(),
+        }
+    }
+
+    fn sample_star(action: &'static str) -> StarEvent<'static> {
+        StarEvent {
+            action: action.into(),
+            sender: Sender {
+                login: "alice".into(),
+                html_url: None,
+            },
+            repository: Repository {
+                stargazers_count: 100,
+                ..sample_repository()
+            },
+        }
+    }
+
+    #[test]
+    fn test_star() {
+        assert_eq!(
+            sample_star("created")
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/alice'><font ",
+                "color='909090'>alice</font></a> starred the repository, ",
+                "which now has 100 stars",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_star_discord() {
+        let embed = sample_star("created")
+            .to_view(view_context(&UsernameAliases::default()))
+            .to_discord();
+        assert_eq!(
+            embed.description,
+            concat!(
+                r"**[\[ExampleCom\]](http://example.com/)** ",
+                "[alice](http://example.com/alice) starred the repository, ",
+                "which now has 100 stars",
+            ),
+        );
+        assert_eq!(embed.color, Some(0xFF00FF));
+    }
+
+    #[test]
+    fn test_star_should_announce() {
+        assert!(sample_star("created").should_announce(None));
+        assert!(sample_star("started").should_announce(None));
+        assert!(!sample_star("deleted").should_announce(None));
+        assert!(sample_star("created").should_announce(Some(100)));
+        assert!(sample_star("created").should_announce(Some(0)));
+        let mut star = sample_star("created");
+        star.repository.stargazers_count = 101;
+        assert!(!star.should_announce(Some(100)));
+    }
+
+    #[test]
+    fn test_fork() {
+        let mut username_aliases = UsernameAliases::default();
+        username_aliases.insert("alice".into(), "Alice".into());
+        let fork = ForkEvent {
+            forkee: Forkee {
+                full_name: "alice/ExampleCom".into(),
+                html_url: "https://github.com/alice/ExampleCom".into(),
+            },
+            sender: Sender {
+                login: "alice".into(),
+                html_url: None,
+            },
+            repository: sample_repository(),
+        };
+        assert_eq!(
+            fork.to_view(view_context(&username_aliases)).to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/alice'><font color='909090'>Alice</font></a> ",
+                "forked the repository to ",
+                "<a href='https://github.com/alice/ExampleCom'>alice/ExampleCom</a>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_member() {
+        let mut username_aliases = UsernameAliases::default();
+        username_aliases.insert("alice".into(), "Alice".into());
+        username_aliases.insert("bob".into(), "Bob".into());
+        let member = |action: &'static str| MemberEvent {
+            action: action.into(),
+            member: Sender {
+                login: "bob".into(),
+                html_url: None,
+            },
+            sender: Sender {
+                login: "alice".into(),
+                html_url: None,
+            },
+            repository: sample_repository(),
+        };
+        assert_eq!(
+            member("added")
+                .to_view(view_context(&username_aliases))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/alice'><font color='909090'>Alice</font></a> ",
+                "added <a href='http://example.com/bob'><font color='909090'>Bob</font></a> ",
+                "as a collaborator",
+            ),
+        );
+        assert_eq!(
+            member("removed")
+                .to_view(view_context(&username_aliases))
+                .to_discord()
+                .description,
+            concat!(
+                "**[\\[ExampleCom\\]](http://example.com/)** ",
+                "[Alice](http://example.com/alice) removed [Bob](http://example.com/bob) ",
+                "from collaborators",
+            ),
+        );
+    }
+
+    fn sample_discussion(action: &'static str) -> DiscussionEvent<'static> {
+        DiscussionEvent {
+            action: action.into(),
+            discussion: Discussion {
+                title: "How do I <add> a format?".into(),
+                html_url: "https://github.com/Example/ExampleCom/discussions/7".into(),
+                category: DiscussionCategory { name: "Q&A".into() },
+            },
+            sender: Sender {
+                login: "bob".into(),
+                html_url: None,
+            },
+            repository: sample_repository(),
+        }
+    }
+
+    #[test]
+    fn test_discussion_created() {
+        let mut username_aliases = UsernameAliases::default();
+        username_aliases.insert("bob".into(), "Bob".into());
+        let discussion = sample_discussion("created");
+        assert!(discussion.should_announce());
+        assert_eq!(
+            discussion
+                .to_view(view_context(&username_aliases))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/bob'><font color='909090'>Bob</font></a> ",
+                "started a discussion in Q&amp;A: ",
+                "<a href='https://github.com/Example/ExampleCom/discussions/7'>",
+                "How do I &lt;add&gt; a format?</a>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_discussion_should_announce() {
+        assert!(sample_discussion("answered").should_announce());
+        assert!(!sample_discussion("edited").should_announce());
+    }
+
+    fn wiki_page(title: &'static str, action: &'static str) -> WikiPage<'static> {
+        WikiPage {
+            page_name: title.replace(' ', "-").into(),
+            title: title.into(),
+            action: action.into(),
+            html_url: format!("https://github.com/Example/ExampleCom/wiki/{}", title).into(),
+        }
+    }
+
+    #[test]
+    fn test_gollum_two_pages() {
+        let mut username_aliases = UsernameAliases::default();
+        username_aliases.insert("alice".into(), "Alice".into());
+        let gollum = GollumEvent {
+            pages: vec![wiki_page("Home", "edited"), wiki_page("FAQ", "created")],
+            sender: Sender {
+                login: "alice".into(),
+                html_url: None,
+            },
+            repository: sample_repository(),
+        };
+        assert!(gollum.should_announce());
+        let view = gollum.to_view(view_context(&username_aliases));
+        assert_eq!(
+            view.to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/alice'><font color='909090'>Alice</font></a> ",
+                "edited wiki page ",
+                "<a href='https://github.com/Example/ExampleCom/wiki/Home'>Home</a>, ",
+                "created wiki page ",
+                "<a href='https://github.com/Example/ExampleCom/wiki/FAQ'>FAQ</a>",
+            ),
+        );
+        assert_eq!(
+            view.to_discord().description,
+            concat!(
+                "**[\\[ExampleCom\\]](http://example.com/)** ",
+                "[Alice](http://example.com/alice) edited wiki page ",
+                "[Home](https://github.com/Example/ExampleCom/wiki/Home), created wiki page ",
+                "[FAQ](https://github.com/Example/ExampleCom/wiki/FAQ)",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_default_branch_changed() {
+        let username_aliases = UsernameAliases::default();
+        let mut event: RepositoryEvent = serde_json::from_str(
+            r#"{
+                "action": "edited",
+                "changes": { "default_branch": { "from": "master" } },
+                "repository": {
+                    "name": "ExampleCom",
+                    "full_name": "ExampleCom/ExampleCom",
+                    "html_url": "http://example.com/",
+                    "default_branch": "main"
+                },
+                "sender": { "login": "alice" }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(event.previous_default_branch(), Some("master"));
+        let view = event
+            .to_default_branch_view(view_context(&username_aliases))
+            .unwrap();
+        assert_eq!(
+            view.to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/alice'><font color='909090'>alice</font></a> ",
+                "changed the default branch from <kbd>master</kbd> to <kbd>main</kbd>",
+            ),
+        );
+        event.changes = RepositoryChanges::default();
+        assert_eq!(event.previous_default_branch(), None);
+        assert!(event
+            .to_default_branch_view(view_context(&username_aliases))
+            .is_none());
+    }
+
+    fn sample_issues_event(
+        action: &'static str,
+        state_reason: Option<&'static str>,
+    ) -> IssuesEvent<'static> {
+        IssuesEvent {
+            action: action.into(),
+            issue: Issue {
+                number: 12,
+                title: "Crash on start".into(),
+                html_url: "http://example.com/issues/12".into(),
+                pull_request: None,
+                state_reason: state_reason.map(Into::into),
+            },
+            sender: sample_sender(),
+            repository: sample_repository(),
+        }
+    }
+
+    fn issue_html(action: &str) -> String {
+        format!(
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/alice'><font color='909090'>alice</font></a> ",
+                "{} <a href='http://example.com/issues/12'>issue #12</a>: Crash on start",
+            ),
+            action,
+        )
+    }
+
+    #[test]
+    fn test_issue_opened() {
+        let username_aliases = UsernameAliases::default();
+        let event = sample_issues_event("opened", None);
+        assert!(event.should_announce());
+        assert_eq!(
+            event.to_view(view_context(&username_aliases)).to_string(),
+            issue_html("<font color=28A745>opened</font>"),
+        );
+        assert!(!sample_issues_event("labeled", None).should_announce());
+    }
+
+    #[test]
+    fn test_issue_closed_as_completed() {
+        let username_aliases = UsernameAliases::default();
+        let event = sample_issues_event("closed", Some("completed"));
+        assert_eq!(
+            event.to_view(view_context(&username_aliases)).to_string(),
+            issue_html("<font color=8250DF>\u{2713} closed</font>"),
+        );
+    }
+
+    #[test]
+    fn test_issue_closed_as_not_planned() {
+        let username_aliases = UsernameAliases::default();
+        let event = sample_issues_event("closed", Some("not_planned"));
+        assert_eq!(
+            event.to_view(view_context(&username_aliases)).to_string(),
+            issue_html("<font color=6A737D>\u{2298} closed as not planned</font>"),
+        );
+    }
+
+    fn sample_deployment_status(state: &'static str) -> DeploymentStatusEvent<'static> {
+        DeploymentStatusEvent {
+            deployment_status: DeploymentStatus {
+                state: state.into(),
+                environment: "production".into(),
+                target_url: Some("http://example.com/deployments/1".into()),
+            },
+            deployment: Deployment {
+                git_ref: "master".into(),
+            },
+            repository: sample_repository(),
+        }
+    }
+
+    #[test]
+    fn test_deployment_succeeded() {
+        let username_aliases = UsernameAliases::default();
+        let event = sample_deployment_status("success");
+        assert!(event.should_announce(None));
+        assert_eq!(
+            event.to_view(view_context(&username_aliases)).to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/deployments/1'>deployment</a> of <kbd>master</kbd> ",
+                "to production <font color=28A745>succeeded</font>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_deployment_failed() {
+        let username_aliases = UsernameAliases::default();
+        let mut event = sample_deployment_status("failure");
+        event.deployment_status.target_url = Some("".into());
+        assert_eq!(
+            event.to_view(view_context(&username_aliases)).to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "deployment of <kbd>master</kbd> to production <font color=CB2431>failed</font>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_deployment_filtering() {
+        let production: HashSet<String> = ["production".to_string()].into_iter().collect();
+        let staging: HashSet<String> = ["staging".to_string()].into_iter().collect();
+        assert!(sample_deployment_status("success").should_announce(Some(&production)));
+        assert!(!sample_deployment_status("success").should_announce(Some(&staging)));
+        assert!(!sample_deployment_status("pending").should_announce(None));
+    }
+
+    fn sample_workflow_run(conclusion: &'static str) -> WorkflowRunEvent<'static> {
+        WorkflowRunEvent {
+            action: "completed".into(),
+            workflow_run: WorkflowRun {
+                name: "CI".into(),
+                conclusion: Some(conclusion.into()),
+                html_url: "http://example.com/actions/runs/1".into(),
+                head_branch: "master".into(),
+            },
+            repository: sample_repository(),
+        }
+    }
+
+    #[test]
+    fn test_workflow_run_success() {
+        assert_eq!(
+            sample_workflow_run("success")
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/actions/runs/1'>CI</a> ",
+                "<font color=28A745>succeeded</font>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_workflow_run_failure() {
+        assert_eq!(
+            sample_workflow_run("failure")
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/actions/runs/1'>CI</a> ",
+                "<font color=CB2431>failed</font>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_workflow_run_should_announce() {
+        let mut event = sample_workflow_run("success");
+        assert!(event.should_announce(None));
+        let workflows = ["Lint".to_string()].into_iter().collect();
+        assert!(!event.should_announce(Some(&workflows)));
+        event.workflow_run.name = "Lint".into();
+        assert!(event.should_announce(Some(&workflows)));
+        event.workflow_run.head_branch = "feature".into();
+        assert!(!event.should_announce(None));
+        event.workflow_run.head_branch = "master".into();
+        event.action = "requested".into();
+        assert!(!event.should_announce(None));
+    }
+
+    fn sample_check_run(conclusion: &'static str) -> CheckRunEvent<'static> {
+        CheckRunEvent {
+            action: "completed".into(),
+            check_run: CheckRun {
+                name: "test (ubuntu-latest, 16.x)".into(),
+                conclusion: Some(conclusion.into()),
+                html_url: "http://example.com/runs/2".into(),
+                head_sha: "0da2590a700d054fc2ce39ddc9c95f360329d9be".into(),
+                check_suite: CheckSuite {
+                    head_branch: Some("master".into()),
+                },
+            },
+            repository: sample_repository(),
+        }
+    }
+
+    #[test]
+    fn test_check_run_failure() {
+        let event = sample_check_run("failure");
+        let username_aliases = UsernameAliases::default();
+        let view = event.to_view(view_context(&username_aliases));
+        assert_eq!(
+            view.to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/runs/2'>test (ubuntu-latest, 16.x)</a> ",
+                "on <kbd>0da259</kbd> <font color=CB2431>failed</font>",
+            ),
+        );
+        assert_eq!(view.to_discord().color, Some(0xCB2431));
+    }
+
+    #[test]
+    fn test_check_run_should_announce() {
+        let mut event = sample_check_run("success");
+        assert!(event.should_announce(None));
+        let check_runs = ["lint".to_string()].into_iter().collect();
+        assert!(!event.should_announce(Some(&check_runs)));
+        event.check_run.name = "lint".into();
+        assert!(event.should_announce(Some(&check_runs)));
+        event.check_run.check_suite.head_branch = None;
+        assert!(!event.should_announce(None));
+        event.check_run.check_suite.head_branch = Some("master".into());
+        event.action = "created".into();
+        assert!(!event.should_announce(None));
+    }
+
+    fn sample_status(state: &'static str) -> StatusEvent<'static> {
+        StatusEvent {
+            state: state.into(),
+            context: "continuous-integration/travis-ci/push".into(),
+            description: Some("The Travis CI build failed".into()),
+            target_url: Some("http://example.com/builds/3".into()),
+            sha: "0da2590a700d054fc2ce39ddc9c95f360329d9be".into(),
+            branches: vec![StatusBranch {
+                name: "master".into(),
+            }],
+            repository: sample_repository(),
+        }
+    }
+
+    #[test]
+    fn test_status_failure() {
+        let event = sample_status("failure");
+        let username_aliases = UsernameAliases::default();
+        let view = event.to_view(view_context(&username_aliases));
+        assert_eq!(
+            view.to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>ExampleCom</font></a>] ",
+                "<a href='http://example.com/builds/3'>continuous-integration/travis-ci/push</a> ",
+                "on <kbd>0da259</kbd> <font color=CB2431>failed</font>: ",
+                "The Travis CI build failed",
+            ),
+        );
+        assert_eq!(view.to_discord().color, Some(0xCB2431));
+    }
+
+    #[test]
+    fn test_status_should_announce() {
+        let mut event = sample_status("success");
+        assert!(event.should_announce(None, None));
+        assert!(!event.should_announce(None, Some("production")));
+        assert!(event.should_announce(None, Some("*")));
+        let contexts = ["lint".to_string()].into_iter().collect();
+        assert!(!event.should_announce(Some(&contexts), None));
+        event.context = "lint".into();
+        assert!(event.should_announce(Some(&contexts), None));
+        event.state = "pending".into();
+        assert!(!event.should_announce(None, None));
+    }
+
+    #[test]
+    fn test_issue_comment_on_pull_request() {
+        assert_eq!(
+            sample_issue_comment(Some(IgnoredAny))
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/alice'><font ",
+                "color='909090'>alice</font></a> ",
+                "<a href='http://example.com/issues/12#comment'>commented</a> on ",
+                "<a href='http://example.com/issues/12'>PR#12</a>: ",
+                "<span title='Looks good'>Looks good</span>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_issue_comment_on_issue() {
+        assert_eq!(
+            sample_issue_comment(None)
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/alice'><font ",
+                "color='909090'>alice</font></a> ",
+                "<a href='http://example.com/issues/12#comment'>commented</a> on ",
+                "<a href='http://example.com/issues/12'>issue #12</a>: ",
+                "<span title='Looks good'>Looks good</span>",
+            ),
+        );
+    }
+
     fn sample_pull_request() -> PullRequestEvent<'static> {
         PullRequestEvent {
             action: "created".into(),
@@ -362,40 +3560,318 @@ mod test {
                 number: 1,
                 html_url: "http://example.com/pr/1".into(),
                 title: "Hello, world".into(),
+                labels: vec![],
+                merged: false,
+                body: None,
+                base: Some(Base {
+                    git_ref: "master".into(),
+                }),
             },
-            repository: Repository {
-                name: "ExampleCom".into(),
-                html_url: "http://example.com/".into(),
-                default_branch: "master".into(),
+            repository: sample_repository(),
+            sender: Sender {
+                login: "Me".into(),
+                html_url: None,
             },
-            sender: Sender { login: "Me".into() },
+            changes: None,
         }
     }
 
     #[test]
-    fn test_pull_request() {
+    fn test_ready_for_review() {
+        let mut pull_request = sample_pull_request();
+        pull_request.action = "ready_for_review".into();
         assert_eq!(
-            sample_pull_request()
-                .to_view(&UsernameAliases::default())
+            pull_request
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
+                "color='909090'>Me</font></a> marked ready for review ",
+                "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_merged_pull_request() {
+        let mut pull_request = sample_pull_request();
+        pull_request.action = "closed".into();
+        pull_request.pull_request.merged = true;
+        assert_eq!(
+            pull_request
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
+                "color='909090'>Me</font></a> <font color=6F42C1>merged</font> ",
+                "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_action_verbs() {
+        let action_verbs: ActionVerbs = serde_json::from_str(
+            r#"{"pull_request": {"synchronize": "pushed to", "merged": "landed"}}"#,
+        )
+        .unwrap();
+        let aliases = UsernameAliases::default();
+        let ctx = ViewContext {
+            action_verbs: &action_verbs,
+            ..view_context(&aliases)
+        };
+        let mut pull_request = sample_pull_request();
+        pull_request.action = "synchronize".into();
+        assert_eq!(pull_request.to_view(ctx).action, "pushed to");
+        pull_request.action = "closed".into();
+        pull_request.pull_request.merged = true;
+        let view = pull_request.to_view(ctx);
+        assert_eq!((view.action, view.action_color), ("landed", Some("6F42C1")));
+        pull_request.action = "review_requested".into();
+        assert_eq!(pull_request.to_view(ctx).action, "requested a review for",);
+    }
+
+    #[test]
+    fn test_closed_pull_request() {
+        let mut pull_request = sample_pull_request();
+        pull_request.action = "closed".into();
+        assert_eq!(
+            pull_request
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
+                "color='909090'>Me</font></a> closed ",
+                "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_pull_request_body_preview() {
+        let mut pull_request = sample_pull_request();
+        pull_request.action = "opened".into();
+        pull_request.pull_request.body = Some(
+            "<!-- Describe your change -->\r\n## Summary\r\n\r\nFixes **Struggle** <damage>.\r\n\r\n- See [the issue](http://example.com/issues/1)\r\n- Third line"
+                .into(),
+        );
+        assert_eq!(
+            pull_request
+                .to_view(view_context(&UsernameAliases::default()))
+                .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
+                "color='909090'>Me</font></a> opened ",
+                "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world",
+                "<br><small>Summary Fixes Struggle &lt;damage&gt;.</small>",
+            ),
+        );
+        pull_request.action = "edited".into();
+        assert!(!pull_request
+            .to_view(view_context(&UsernameAliases::default()))
+            .to_string()
+            .contains("<small>"));
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview("", 10), None);
+        assert_eq!(preview("<!-- Nothing -->\n", 10), None);
+        assert_eq!(preview("Short", 0), None);
+        assert_eq!(preview("`a` and _b_", 100).as_deref(), Some("a and _b_"));
+        assert_eq!(preview("A long description", 6).as_deref(), Some("A long…"));
+    }
+
+    #[test]
+    fn test_pull_request_null_body() {
+        let pull_request: PullRequest = serde_json::from_str(
+            r#"{"number": 1, "html_url": "http://example.com/pr/1", "title": "A", "body": null}"#,
+        )
+        .unwrap();
+        assert!(pull_request.body.is_none());
+    }
+
+    #[test]
+    fn test_pull_request_renamed() {
+        let mut pull_request = sample_pull_request();
+        pull_request.action = "edited".into();
+        pull_request.changes = Some(Changes {
+            title: Some(Change {
+                from: "Helo, wrld".into(),
+            }),
+        });
+        assert!(!pull_request.is_untitled_edit());
+        assert_eq!(
+            pull_request
+                .to_view(view_context(&UsernameAliases::default()))
                 .to_string(),
             concat!(
                 "[<a href='http://example.com/'><font color=FF00FF>",
-                "ExampleCom</font></a>] <a href='https://github.com/Me'><font ",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
+                "color='909090'>Me</font></a> renamed ",
+                "<a href='http://example.com/pr/1'>PR#1</a> from 'Helo, wrld' ",
+                "to 'Hello, world'",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_pull_request_body_edit() {
+        let mut pull_request: PullRequestEvent = serde_json::from_str(
+            r#"{
+                "action": "edited",
+                "changes": {"body": {"from": "Old description"}},
+                "pull_request": {"number": 1, "html_url": "http://example.com/pr/1", "title": "A"},
+                "repository": {
+                    "name": "ExampleCom",
+                    "full_name": "Example/ExampleCom",
+                    "html_url": "http://example.com/",
+                    "default_branch": "master"
+                },
+                "sender": {"login": "Me"}
+            }"#,
+        )
+        .unwrap();
+        assert!(pull_request.is_untitled_edit());
+        pull_request.action = "opened".into();
+        assert!(!pull_request.is_untitled_edit());
+    }
+
+    #[test]
+    fn test_merged_into() {
+        let mut pull_request = sample_pull_request();
+        assert_eq!(pull_request.merged_into(), None);
+        pull_request.action = "closed".into();
+        assert_eq!(pull_request.merged_into(), None);
+        pull_request.pull_request.merged = true;
+        assert_eq!(pull_request.merged_into(), Some("master"));
+    }
+
+    #[test]
+    fn test_pull_request_with_labels() {
+        let mut pull_request = sample_pull_request();
+        pull_request.pull_request.labels = vec![
+            Label {
+                name: "Bug".into(),
+                color: Some("d73a4a".into()),
+            },
+            Label {
+                name: "<Tests>".into(),
+                color: Some("0e8a16".into()),
+            },
+            serde_json::from_str(r#"{"name": "CSS", "color": "red;position:fixed"}"#).unwrap(),
+        ];
+        assert_eq!(
+            pull_request.to_view(view_context(&UsernameAliases::default())).to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
+                "color='909090'>Me</font></a> created ",
+                "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world ",
+                "<span style='background:#d73a4a;border-radius:4px;padding:0 4px'>Bug</span> ",
+                "<span style='background:#0e8a16;border-radius:4px;padding:0 4px'>&lt;Tests&gt;</span> ",
+                "<span>CSS</span>",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_pull_request_review() {
+        let sample = sample_pull_request();
+        let mut aliases = UsernameAliases::default();
+        aliases.insert("alice".into(), "Alice".into());
+        assert_eq!(
+            PullRequestReviewEvent {
+                action: "submitted".into(),
+                review: Review {
+                    state: "approved".into(),
+                    user: Sender {
+                        login: "alice".into(),
+                        html_url: None,
+                    },
+                    body: None,
+                },
+                pull_request: sample.pull_request,
+                repository: sample.repository,
+            }
+            .to_view(view_context(&aliases))
+            .to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=FF00FF>",
+                "ExampleCom</font></a>] <a href='http://example.com/alice'><font ",
+                "color='909090'>Alice</font></a> approved ",
+                "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world",
+            ),
+        );
+    }
+
+    #[test]
+    fn test_pull_request_with_repository_color() {
+        let aliases = UsernameAliases::default();
+        let ctx = ViewContext {
+            repository_color: "00AA00",
+            ..view_context(&aliases)
+        };
+        assert_eq!(
+            sample_pull_request().to_view(ctx).to_string(),
+            concat!(
+                "[<a href='http://example.com/'><font color=00AA00>",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
                 "color='909090'>Me</font></a> created ",
                 "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world",
             ),
         );
     }
 
+    #[test]
+    fn test_repository_alias() {
+        let aliases = UsernameAliases::default();
+        let mut repository_aliases = HashMap::new();
+        repository_aliases.insert("Example/ExampleCom".to_string(), "example".to_string());
+        let ctx = ViewContext {
+            repository_aliases: &repository_aliases,
+            ..view_context(&aliases)
+        };
+        assert_eq!(
+            sample_repository().to_view(ctx).to_string(),
+            "[<a href='http://example.com/'><font color=FF00FF>example</font></a>]",
+        );
+    }
+
+    #[test]
+    fn test_showdown_repository_names() {
+        let aliases = UsernameAliases::default();
+        let repository = Repository {
+            name: "pokemon-showdown".into(),
+            full_name: "smogon/pokemon-showdown".into(),
+            ..sample_repository()
+        };
+        assert_eq!(repository.to_view(view_context(&aliases)).name, "server");
+        let ctx = ViewContext {
+            showdown_repository_names: false,
+            ..view_context(&aliases)
+        };
+        assert_eq!(
+            repository.to_view(ctx).to_string(),
+            "[<a href='http://example.com/'><font color=FF00FF>pokemon-showdown</font></a>]",
+        );
+    }
+
     #[test]
     fn test_pull_request_with_an_alias() {
         let mut aliases = UsernameAliases::default();
         aliases.insert("mE".into(), "Not me".into());
         assert_eq!(
-            sample_pull_request().to_view(&aliases).to_string(),
+            sample_pull_request()
+                .to_view(view_context(&aliases))
+                .to_string(),
             concat!(
                 "[<a href='http://example.com/'><font color=FF00FF>",
-                "ExampleCom</font></a>] <a href='https://github.com/Me'><font ",
+                "ExampleCom</font></a>] <a href='http://example.com/Me'><font ",
                 "color='909090'>Not me</font></a> created ",
                 "<a href='http://example.com/pr/1'>PR#1</a>: Hello, world",
             ),
@@ -0,0 +1,233 @@
+//! Deserialization that reports top-level fields of a payload that were
+//! ignored, so that changes in GitHub payloads can be noticed.
+
+use serde::de::{
+    self, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::Deserialize;
+use std::fmt::{self, Formatter};
+
+/// Deserializes `input`, also returning names of ignored top-level fields.
+pub fn from_slice<'de, T: Deserialize<'de>>(
+    input: &'de [u8],
+) -> serde_json::Result<(T, Vec<String>)> {
+    let mut ignored = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_slice(input);
+    let value = T::deserialize(TopLevel {
+        inner: &mut deserializer,
+        ignored: &mut ignored,
+    })?;
+    deserializer.end()?;
+    Ok((value, ignored))
+}
+
+/// Forwards every `deserialize_*` method not listed explicitly to `inner`.
+macro_rules! forward_deserialize {
+    ($($method:ident($($argument:ident: $type:ty),*))*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($argument: $type,)*
+                visitor: V,
+            ) -> Result<V::Value, Self::Error> {
+                self.inner.$method($($argument,)* visitor)
+            }
+        )*
+    };
+    (all except $($skipped:ident)*) => {
+        forward_deserialize! {
+            deserialize_any() deserialize_bool() deserialize_i8() deserialize_i16()
+            deserialize_i32() deserialize_i64() deserialize_u8() deserialize_u16()
+            deserialize_u32() deserialize_u64() deserialize_f32() deserialize_f64()
+            deserialize_char() deserialize_str() deserialize_string() deserialize_bytes()
+            deserialize_byte_buf() deserialize_option() deserialize_unit()
+            deserialize_unit_struct(name: &'static str)
+            deserialize_newtype_struct(name: &'static str)
+            deserialize_seq() deserialize_tuple(len: usize)
+            deserialize_tuple_struct(name: &'static str, len: usize)
+            deserialize_identifier()
+            deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        }
+        forward_deserialize! { @skipped $($skipped)* }
+    };
+    (@skipped deserialize_map deserialize_struct) => {
+        forward_deserialize! { deserialize_ignored_any() }
+    };
+    (@skipped deserialize_ignored_any) => {
+        forward_deserialize! {
+            deserialize_map()
+            deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        }
+    };
+}
+
+/// The payload itself, which watches fields of the map it contains.
+struct TopLevel<'a, D> {
+    inner: D,
+    ignored: &'a mut Vec<String>,
+}
+
+impl<'de, 'a, D: Deserializer<'de>> Deserializer<'de> for TopLevel<'a, D> {
+    type Error = D::Error;
+
+    forward_deserialize! { all except deserialize_map deserialize_struct }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.inner.deserialize_map(Watched {
+            inner: visitor,
+            ignored: self.ignored,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let visitor = Watched {
+            inner: visitor,
+            ignored: self.ignored,
+        };
+        self.inner.deserialize_struct(name, fields, visitor)
+    }
+}
+
+struct Watched<'a, V> {
+    inner: V,
+    ignored: &'a mut Vec<String>,
+}
+
+impl<'de, 'a, V: Visitor<'de>> Visitor<'de> for Watched<'a, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_map(WatchedMap {
+            inner: map,
+            key: String::new(),
+            ignored: self.ignored,
+        })
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_seq(seq)
+    }
+}
+
+struct WatchedMap<'a, A> {
+    inner: A,
+    key: String,
+    ignored: &'a mut Vec<String>,
+}
+
+impl<'de, 'a, A: MapAccess<'de>> MapAccess<'de> for WatchedMap<'a, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        match self.inner.next_key::<String>()? {
+            Some(key) => {
+                self.key = key;
+                let key: de::value::StrDeserializer<A::Error> =
+                    self.key.as_str().into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, A::Error> {
+        self.inner.next_value_seed(Field {
+            inner: seed,
+            key: &self.key,
+            ignored: self.ignored,
+        })
+    }
+}
+
+/// A value of a top-level field, which is ignored when the type being
+/// deserialized asks to skip it.
+struct Field<'a, S> {
+    inner: S,
+    key: &'a str,
+    ignored: &'a mut Vec<String>,
+}
+
+impl<'de, 'a, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Field<'a, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        self.inner.deserialize(Field {
+            inner: deserializer,
+            key: self.key,
+            ignored: self.ignored,
+        })
+    }
+}
+
+impl<'de, 'a, D: Deserializer<'de>> Deserializer<'de> for Field<'a, D> {
+    type Error = D::Error;
+
+    forward_deserialize! { all except deserialize_ignored_any }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.ignored.push(self.key.to_string());
+        self.inner.deserialize_ignored_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::from_slice;
+    use serde::Deserialize;
+    use std::borrow::Cow;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Event<'a> {
+        #[serde(borrow)]
+        action: Cow<'a, str>,
+        number: Option<u32>,
+        labels: Vec<String>,
+    }
+
+    #[test]
+    fn test_ignored_fields() {
+        let input = br#"{
+            "action": "opened",
+            "draft": false,
+            "number": null,
+            "labels": ["bug"],
+            "sender": { "login": "xfix", "id": 1 }
+        }"#;
+        let (event, ignored) = from_slice::<Event>(input).unwrap();
+        assert_eq!(
+            event,
+            Event {
+                action: "opened".into(),
+                number: None,
+                labels: vec!["bug".into()],
+            },
+        );
+        assert_eq!(ignored, ["draft", "sender"]);
+    }
+
+    #[test]
+    fn test_no_ignored_fields() {
+        let input = br#"{"action": "closed", "number": 5, "labels": []}"#;
+        let (event, ignored) = from_slice::<Event>(input).unwrap();
+        assert_eq!(event.number, Some(5));
+        assert!(ignored.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_payload() {
+        assert!(from_slice::<Event>(br#"{"action": 1}"#).is_err());
+        assert!(from_slice::<Event>(br#"{"action": "closed", "labels": []} x"#).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! RFC 3339 timestamps, like `2021-05-01T12:00:00+02:00` in commits of push
+//! payloads.
+
+use std::ops::Range;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Parses an RFC 3339 timestamp into seconds since the Unix epoch.
+pub fn parse_rfc3339(timestamp: &str) -> Option<i64> {
+    let bytes = timestamp.as_bytes();
+    let number = |range: Range<usize>| parse_digits(bytes.get(range)?);
+    let separator = |index: usize, allowed: &[u8]| matches!(bytes.get(index), Some(byte) if allowed.contains(byte));
+    if !(separator(4, b"-")
+        && separator(7, b"-")
+        && separator(10, b"Tt ")
+        && separator(13, b":")
+        && separator(16, b":"))
+    {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let mut rest = &bytes[19..];
+    if let Some((b'.', fraction)) = rest.split_first() {
+        let digits = fraction.iter().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), hours @ .., b':', _, _] if hours.len() == 2 => {
+            let (hours, minutes) = (parse_digits(hours)?, parse_digits(&rest[4..])?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+    let days = days_from_civil(year, month, day);
+    Some(days * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second - offset)
+}
+
+fn parse_digits(digits: &[u8]) -> Option<i64> {
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(
+        digits
+            .iter()
+            .fold(0, |n, &digit| n * 10 + i64::from(digit - b'0')),
+    )
+}
+
+/// Formats seconds since the Unix epoch as a UTC time, like
+/// `2021-05-01 10:00 UTC`.
+pub fn format_utc(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(SECONDS_PER_DAY);
+    let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+    )
+}
+
+/// Describes how long ago `timestamp` was, like `2h ago`.
+pub fn format_relative(timestamp: i64, now: i64) -> String {
+    let elapsed = now - timestamp;
+    if elapsed < 60 {
+        "just now".into()
+    } else if elapsed < 60 * 60 {
+        format!("{}m ago", elapsed / 60)
+    } else if elapsed < SECONDS_PER_DAY {
+        format!("{}h ago", elapsed / (60 * 60))
+    } else {
+        format!("{}d ago", elapsed / SECONDS_PER_DAY)
+    }
+}
+
+/// Days since the Unix epoch of a date in the proleptic Gregorian calendar,
+/// using Howard Hinnant's algorithm.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_relative, format_utc, parse_rfc3339};
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_rfc3339("2021-05-01T10:00:00Z"), Some(1_619_863_200));
+        assert_eq!(
+            parse_rfc3339("2021-05-01T12:00:00+02:00"),
+            Some(1_619_863_200),
+        );
+        assert_eq!(
+            parse_rfc3339("2021-04-30T22:30:00.123-11:30"),
+            Some(1_619_863_200),
+        );
+        assert_eq!(parse_rfc3339("2024-02-29t00:00:00z"), Some(1_709_164_800));
+        for invalid in [
+            "",
+            "2021-05-01",
+            "2021-05-01T12:00:00",
+            "2021-13-01T12:00:00Z",
+            "2021-05-01T12:00:00+2:00",
+            "2021-05-01T12:00:00.Z",
+            "2021-05-01T12:00:00Z ",
+        ] {
+            assert_eq!(parse_rfc3339(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00 UTC");
+        assert_eq!(format_utc(1_709_164_800 + 90), "2024-02-29 00:01 UTC");
+    }
+
+    #[test]
+    fn test_format_relative() {
+        let now = 1_619_863_200;
+        assert_eq!(format_relative(now - 30, now), "just now");
+        assert_eq!(format_relative(now - 5 * 60, now), "5m ago");
+        assert_eq!(format_relative(now - 2 * 60 * 60 - 1, now), "2h ago");
+        assert_eq!(format_relative(now - 3 * 24 * 60 * 60, now), "3d ago");
+        // Clocks of committers aren't always right.
+        assert_eq!(format_relative(now + 60 * 60, now), "just now");
+    }
+}